@@ -0,0 +1,25 @@
+//! Build information for `dptran --build-info`
+
+use std::env;
+use std::process::Command;
+
+fn main() {
+    let target = env::var("TARGET").unwrap_or_else(|_| "unknown".to_string());
+
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = Command::new(rustc).arg("--version").output().ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|version| version.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    // Enabled features are passed to the build script as CARGO_FEATURE_<NAME>
+    let mut features = env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(|feature| feature.to_lowercase().replace('_', "-")))
+        .collect::<Vec<String>>();
+    features.sort();
+
+    println!("cargo:rustc-env=DPTRAN_BUILD_TARGET={}", target);
+    println!("cargo:rustc-env=DPTRAN_RUSTC_VERSION={}", rustc_version);
+    println!("cargo:rustc-env=DPTRAN_FEATURES={}", features.join(","));
+    println!("cargo:rerun-if-changed=build.rs");
+}
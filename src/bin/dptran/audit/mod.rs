@@ -0,0 +1,66 @@
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+
+/// A record of the audit log.
+/// The texts themselves are never recorded.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct AuditRecord {
+    /// Seconds since the UNIX epoch
+    pub timestamp: u64,
+    /// Source language ("auto" if detected by DeepL API)
+    pub source_lang: String,
+    pub target_lang: String,
+    /// Number of characters sent
+    pub chars: usize,
+    pub endpoint: String,
+    pub success: bool,
+}
+
+impl AuditRecord {
+    /// Create a record of a request sent now.
+    pub fn new(source_lang: &Option<String>, target_lang: &str, chars: usize, endpoint: &str, success: bool) -> AuditRecord {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        AuditRecord {
+            timestamp,
+            source_lang: source_lang.as_deref().unwrap_or("auto").to_string(),
+            target_lang: target_lang.to_string(),
+            chars,
+            endpoint: endpoint.to_string(),
+            success,
+        }
+    }
+}
+
+/// Append the record to the audit log as a line of JSON.
+/// The file is created if it does not exist.
+pub fn append(path: &Path, record: &AuditRecord) -> io::Result<()> {
+    let line = serde_json::to_string(record)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)
+}
+
+#[test]
+fn append_test() {
+    let path = std::env::temp_dir().join(format!("dptran_audit_test_{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    append(&path, &AuditRecord::new(&None, "JA", 13, dptran::DEEPL_API_TRANSLATE, true)).unwrap();
+    append(&path, &AuditRecord::new(&Some("EN".to_string()), "DE", 5, dptran::DEEPL_API_TRANSLATE, false)).unwrap();
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let records = log.lines().map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()).collect::<Vec<_>>();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["source_lang"], "auto");
+    assert_eq!(records[0]["target_lang"], "JA");
+    assert_eq!(records[0]["chars"], 13);
+    assert_eq!(records[0]["success"], true);
+    assert_eq!(records[1]["source_lang"], "EN");
+    assert_eq!(records[1]["target_lang"], "DE");
+    assert_eq!(records[1]["success"], false);
+    // The texts are not recorded
+    assert!(records[0].get("text").is_none());
+}
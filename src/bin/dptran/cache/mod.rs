@@ -1,8 +1,21 @@
 use std::collections::HashMap;
 use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
 use serde::{Deserialize, Serialize};
 use confy;
 use md5;
+use dptran::LanguageCodesWithEtag;
+
+/// Cache file name
+/// Tests use a separate file so as not to overwrite the user's cache.
+#[cfg(not(test))]
+const CACHE_NAME: &str = "cache";
+#[cfg(test)]
+const CACHE_NAME: &str = "cache_test";
+
+/// Lock for the tests sharing the cache file
+#[cfg(test)]
+pub static TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 struct CacheElement {
@@ -10,6 +23,9 @@ struct CacheElement {
     pub source_langcode: Option<String>,
     pub target_langcode: String,
     pub value: String,
+    /// Seconds since the UNIX epoch when the entry was stored (0: stored by an older version)
+    #[serde(default)]
+    pub created_at: u64,
 }
 
 // Cache struct
@@ -17,12 +33,16 @@ struct CacheElement {
 struct Cache {
     pub saved_version: String,
     pub elements: HashMap<String, CacheElement>,
+    /// Language code lists with their ETags ("source" / "target")
+    #[serde(default)]
+    pub languages: HashMap<String, LanguageCodesWithEtag>,
 }
 impl Default for Cache {
     fn default() -> Self {
         Self {
             saved_version: env!("CARGO_PKG_VERSION").to_string(),
             elements: HashMap::new(),
+            languages: HashMap::new(),
         }
     }
 }
@@ -31,34 +51,82 @@ impl Default for Cache {
 #[derive(Debug, PartialEq)]
 pub enum CacheError {
     FailToReadCache(String),
+    FailToExportCache(String),
+    FailToImportCache(String),
 }
 impl fmt::Display for CacheError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             CacheError::FailToReadCache(ref e) => write!(f, "Failed to read cache: {}", e),
+            CacheError::FailToExportCache(ref e) => write!(f, "Failed to export cache: {}", e),
+            CacheError::FailToImportCache(ref e) => write!(f, "Failed to import cache: {}", e),
         }
     }
 }
 
+/// Get cache file path
+pub fn get_cache_file_path() -> Result<std::path::PathBuf, CacheError> {
+    confy::get_configuration_file_path("dptran", CACHE_NAME).map_err(|e| CacheError::FailToReadCache(e.to_string()))
+}
+
 fn get_cache_data() -> Result<Cache, CacheError> {
-    confy::load::<Cache>("dptran", "cache").map_err(|e| CacheError::FailToReadCache(e.to_string()))
+    confy::load::<Cache>("dptran", CACHE_NAME).map_err(|e| CacheError::FailToReadCache(e.to_string()))
 }
 
 fn save_cache_data(cache_data: Cache) -> Result<(), CacheError> {
-    confy::store("dptran", "cache", cache_data).map_err(|e| CacheError::FailToReadCache(e.to_string()))
+    confy::store("dptran", CACHE_NAME, cache_data).map_err(|e| CacheError::FailToReadCache(e.to_string()))
+}
+
+/// Seconds since the UNIX epoch.
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Normalize the language code so that the cache does not depend on its casing (e.g. "ja" and "JA").
+fn normalize_lang_code(lang_code: &str) -> String {
+    lang_code.to_uppercase()
+}
+
+/// Get the cached language code list ("source" / "target").
+pub fn get_cached_language_codes(type_name: &str) -> Result<Option<LanguageCodesWithEtag>, CacheError> {
+    let cache_data = get_cache_data()?;
+    Ok(cache_data.languages.get(type_name).cloned())
+}
+
+/// Store the language code list ("source" / "target").
+pub fn store_language_codes(type_name: &str, lang_codes: &LanguageCodesWithEtag) -> Result<(), CacheError> {
+    let mut cache_data = get_cache_data()?;
+    cache_data.languages.insert(type_name.to_string(), lang_codes.clone());
+    save_cache_data(cache_data)
+}
+
+/// Normalize the whitespace of the text for the cache key.
+/// Each line is trimmed and the runs of spaces and tabs are collapsed into one space. The line breaks are kept.
+pub fn normalize_whitespace(text: &str) -> String {
+    text.trim()
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .collect::<Vec<String>>()
+        .join("\n")
 }
 
-fn cache_hash(text: &String, source_lang: &Option<String>, target_lang: &String) -> String {
+/// Key (md5 hash) of the cache entry for the text and the languages.
+/// scope distinguishes the translations with the parameters affecting the result (e.g. glossary and formality).
+/// An empty scope gives the same key as the entries without parameters.
+pub fn cache_hash(text: &str, source_lang: &Option<String>, target_lang: &str, scope: &str) -> String {
     let mut s = format!("text:{}:", text);
-    if source_lang.is_some() {
-        s.push_str(format!(":source:{}", target_lang).as_str());
+    if let Some(source_lang) = source_lang {
+        s.push_str(format!(":source:{}", normalize_lang_code(source_lang)).as_str());
+    }
+    s.push_str(format!("target:{}", normalize_lang_code(target_lang)).as_str());
+    if !scope.is_empty() {
+        s.push_str(format!(":scope:{}", scope).as_str());
     }
-    s.push_str(format!("target:{}", target_lang).as_str());
     let hash = md5::compute(s.as_bytes());
     format!("{:x}", hash)
 }
 
-pub fn into_cache_element(source_text: &String, value: &String, source_lang: &Option<String>, target_lang: &String, max_entries: usize) -> Result<(), CacheError> {
+pub fn into_cache_element(source_text: &str, value: &str, source_lang: &Option<String>, target_lang: &str, scope: &str, max_entries: usize) -> Result<(), CacheError> {
     // read cache data file
     let mut cache_data = get_cache_data()?;
     // if caches are more than max_entries, remove the oldest one
@@ -68,17 +136,15 @@ pub fn into_cache_element(source_text: &String, value: &String, source_lang: &Op
             cache_data.elements.remove(&oldest_key);
         }
     }
-    // clone source_text and value
-    let s = source_text.clone();
-    let v = value.clone();
     // create key by md5
-    let key = cache_hash(&s, source_lang, target_lang);
+    let key = cache_hash(source_text, source_lang, target_lang, scope);
     // create cache element
     let element = CacheElement {
         key: key.clone(),
-        source_langcode: source_lang.clone(),
-        target_langcode: target_lang.clone(),
-        value: v,
+        source_langcode: source_lang.as_deref().map(normalize_lang_code),
+        target_langcode: normalize_lang_code(target_lang),
+        value: value.to_string(),
+        created_at: now(),
     };
     // insert element into cache_data
     cache_data.elements.insert(key, element);
@@ -87,28 +153,223 @@ pub fn into_cache_element(source_text: &String, value: &String, source_lang: &Op
     Ok(())
 }
 
-pub fn search_cache(value: &String, source_lang: &Option<String>, target_lang: &String) -> Result<Option<String>, CacheError> {
+pub fn search_cache(value: &str, source_lang: &Option<String>, target_lang: &str, scope: &str) -> Result<Option<String>, CacheError> {
     let cache_data = get_cache_data()?;
-    let v = value.clone();
-    let key = cache_hash(&v, source_lang, target_lang);
+    let key = cache_hash(value, source_lang, target_lang, scope);
+    let source_lang = source_lang.as_deref().map(normalize_lang_code);
+    let target_lang = normalize_lang_code(target_lang);
 
     if let Some(element) = cache_data.elements.get(&key) {
-        if source_lang.is_none() {
-            if element.target_langcode == *target_lang && element.source_langcode.is_none() {
-                return Ok(Some(element.value.clone()));
-            }
-        }
-        else if element.source_langcode.is_some() {
-            if element.target_langcode == *target_lang && element.source_langcode.as_ref().unwrap() == source_lang.as_ref().unwrap() {
-                return Ok(Some(element.value.clone()));
-            }
+        if element.target_langcode == target_lang && element.source_langcode == source_lang {
+            return Ok(Some(element.value.clone()));
         }
     }
 
     Ok(None)
 }
 
+/// Get the number of cache entries
+pub fn get_cache_entries_count() -> Result<usize, CacheError> {
+    let cache_data = get_cache_data()?;
+    Ok(cache_data.elements.len())
+}
+
+/// Cache entry: (hash of the source text, source language, target language, translation).
+/// The source language is None if it was detected automatically.
+pub type CacheEntry = (String, Option<String>, String, String);
+
+/// Get the cache entries sorted by the hash.
+pub fn get_cache_entries() -> Result<Vec<CacheEntry>, CacheError> {
+    let cache_data = get_cache_data()?;
+    let mut entries = cache_data.elements.into_values()
+        .map(|element| (element.key, element.source_langcode, element.target_langcode, element.value))
+        .collect::<Vec<CacheEntry>>();
+    entries.sort();
+    Ok(entries)
+}
+
+/// Export the cache entries to a JSON file.
+/// Returns the number of the exported entries.
+pub fn export_cache(path: &std::path::Path) -> Result<usize, CacheError> {
+    let cache_data = get_cache_data()?;
+    let json = serde_json::to_string_pretty(&cache_data.elements).map_err(|e| CacheError::FailToExportCache(e.to_string()))?;
+    std::fs::write(path, json).map_err(|e| CacheError::FailToExportCache(e.to_string()))?;
+    Ok(cache_data.elements.len())
+}
+
+/// Merge the cache entries in the JSON file exported by ``export_cache()`` into the cache.
+/// The existing entries are kept, and no more entries are imported once the cache has max_entries entries.
+/// Returns the number of the imported entries.
+pub fn import_cache(path: &std::path::Path, max_entries: usize) -> Result<usize, CacheError> {
+    let json = std::fs::read_to_string(path).map_err(|e| CacheError::FailToImportCache(e.to_string()))?;
+    let elements: HashMap<String, CacheElement> = serde_json::from_str(&json).map_err(|e| CacheError::FailToImportCache(e.to_string()))?;
+    let mut cache_data = get_cache_data()?;
+    let mut imported = 0;
+    for (key, element) in elements {
+        if cache_data.elements.len() >= max_entries {
+            break;
+        }
+        if let std::collections::hash_map::Entry::Vacant(entry) = cache_data.elements.entry(key) {
+            entry.insert(element);
+            imported += 1;
+        }
+    }
+    save_cache_data(cache_data)?;
+    Ok(imported)
+}
+
+/// Remove the cache entries matching all the specified conditions:
+/// translated into target_lang, from source_lang, and stored more than older_than seconds ago.
+/// The entries stored by older versions have no timestamp and are treated as old.
+/// Returns the number of the removed entries.
+pub fn prune_cache(source_lang: &Option<String>, target_lang: &Option<String>, older_than: Option<u64>) -> Result<usize, CacheError> {
+    let mut cache_data = get_cache_data()?;
+    let source_lang = source_lang.as_deref().map(normalize_lang_code);
+    let target_lang = target_lang.as_deref().map(normalize_lang_code);
+    let threshold = older_than.map(|older_than| now().saturating_sub(older_than));
+    let before = cache_data.elements.len();
+    cache_data.elements.retain(|_, element| {
+        let matched = (target_lang.is_none() || target_lang.as_ref() == Some(&element.target_langcode))
+            && (source_lang.is_none() || element.source_langcode == source_lang)
+            && !matches!(threshold, Some(threshold) if element.created_at >= threshold);
+        !matched
+    });
+    let removed = before - cache_data.elements.len();
+    save_cache_data(cache_data)?;
+    Ok(removed)
+}
+
 pub fn clear_cache() -> Result<(), CacheError> {
     let cache_data = Cache::default();
     save_cache_data(cache_data)
 }
+
+#[test]
+fn lang_code_casing_test() {
+    let _lock = TEST_LOCK.lock().unwrap();
+    let text = "lang_code_casing_test".to_string();
+    into_cache_element(&text, "translated", &Some("en".to_string()), "ja", "", 100).unwrap();
+    assert_eq!(search_cache(&text, &Some("EN".to_string()), "JA", "").unwrap(), Some("translated".to_string()));
+    // The source language is a part of the key
+    assert_eq!(search_cache(&text, &Some("DE".to_string()), "JA", "").unwrap(), None);
+}
+
+#[test]
+fn normalize_whitespace_test() {
+    let _lock = TEST_LOCK.lock().unwrap();
+    assert_eq!(normalize_whitespace("  Hello,   world! \n\tGood  morning.  "), "Hello, world!\nGood morning.");
+    // The case is kept
+    assert_eq!(normalize_whitespace("Hello "), "Hello");
+
+    // "Hello " hits the entry stored for "Hello"
+    let target_lang = "JA";
+    into_cache_element(&normalize_whitespace("Hello"), "こんにちは", &None, target_lang, "", 100).unwrap();
+    assert_eq!(search_cache(&normalize_whitespace("Hello "), &None, target_lang, "").unwrap(), Some("こんにちは".to_string()));
+    assert_eq!(search_cache(&normalize_whitespace("  Hello"), &None, target_lang, "").unwrap(), Some("こんにちは".to_string()));
+}
+
+#[test]
+fn language_codes_cache_test() {
+    let _lock = TEST_LOCK.lock().unwrap();
+    let lang_codes = LanguageCodesWithEtag {
+        etag: Some("\"v1\"".to_string()),
+        lang_codes: vec![(("DE".to_string(), "German".to_string()), true), (("JA".to_string(), "Japanese".to_string()), true)],
+    };
+    store_language_codes("target", &lang_codes).unwrap();
+    assert_eq!(get_cached_language_codes("target").unwrap(), Some(lang_codes));
+}
+
+#[test]
+fn export_import_test() {
+    let _lock = TEST_LOCK.lock().unwrap();
+    let path = std::env::temp_dir().join(format!("dptran_cache_export_test_{}.json", std::process::id()));
+    clear_cache().unwrap();
+    into_cache_element("Good morning.", "おはようございます。", &None, "JA", "", 100).unwrap();
+    into_cache_element("Thank you.", "Danke.", &Some("EN".to_string()), "DE", "", 100).unwrap();
+    assert_eq!(export_cache(&path).unwrap(), 2);
+
+    clear_cache().unwrap();
+    assert_eq!(search_cache("Good morning.", &None, "JA", "").unwrap(), None);
+    assert_eq!(import_cache(&path, 100).unwrap(), 2);
+    assert_eq!(search_cache("Good morning.", &None, "JA", "").unwrap(), Some("おはようございます。".to_string()));
+    assert_eq!(search_cache("Thank you.", &Some("EN".to_string()), "DE", "").unwrap(), Some("Danke.".to_string()));
+    // The existing entries are not imported twice
+    assert_eq!(import_cache(&path, 100).unwrap(), 0);
+
+    // max_entries is not exceeded
+    clear_cache().unwrap();
+    assert_eq!(import_cache(&path, 1).unwrap(), 1);
+    assert_eq!(get_cache_entries_count().unwrap(), 1);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn scope_test() {
+    let _lock = TEST_LOCK.lock().unwrap();
+    let text = "scope_test".to_string();
+    let scope = "glossary:def3a26b-3e84-45b3-84ae-0c0aaf3525f7";
+    into_cache_element(&text, "with glossary", &None, "JA", scope, 100).unwrap();
+    assert_eq!(search_cache(&text, &None, "JA", scope).unwrap(), Some("with glossary".to_string()));
+    // The translation without the glossary is not returned
+    assert_eq!(search_cache(&text, &None, "JA", "").unwrap(), None);
+    assert_eq!(search_cache(&text, &None, "JA", "glossary:another").unwrap(), None);
+    assert_ne!(cache_hash(&text, &None, "JA", scope), cache_hash(&text, &None, "JA", ""));
+}
+
+#[test]
+fn prune_cache_test() {
+    let _lock = TEST_LOCK.lock().unwrap();
+    clear_cache().unwrap();
+    let text = "prune_cache_test".to_string();
+    into_cache_element(&text, "ja", &Some("EN".to_string()), "JA", "", 100).unwrap();
+    into_cache_element(&text, "ja (detected)", &None, "JA", "", 100).unwrap();
+    into_cache_element(&text, "de", &Some("EN".to_string()), "DE", "", 100).unwrap();
+
+    // Only the entries from EN
+    assert_eq!(prune_cache(&Some("en".to_string()), &Some("ja".to_string()), None).unwrap(), 1);
+    assert_eq!(search_cache(&text, &None, "JA", "").unwrap(), Some("ja (detected)".to_string()));
+    // All the entries into JA
+    assert_eq!(prune_cache(&None, &Some("JA".to_string()), None).unwrap(), 1);
+    assert_eq!(search_cache(&text, &None, "JA", "").unwrap(), None);
+    // The other target language remains
+    assert_eq!(search_cache(&text, &Some("EN".to_string()), "DE", "").unwrap(), Some("de".to_string()));
+    assert_eq!(get_cache_entries_count().unwrap(), 1);
+    clear_cache().unwrap();
+}
+
+#[test]
+fn get_cache_entries_test() {
+    let _lock = TEST_LOCK.lock().unwrap();
+    clear_cache().unwrap();
+    let text = "get_cache_entries_test".to_string();
+    into_cache_element(&text, "ja", &Some("en".to_string()), "ja", "", 100).unwrap();
+    into_cache_element(&text, "de", &None, "DE", "", 100).unwrap();
+
+    let mut expected = vec![
+        (cache_hash(&text, &Some("en".to_string()), "ja", ""), Some("EN".to_string()), "JA".to_string(), "ja".to_string()),
+        (cache_hash(&text, &None, "DE", ""), None, "DE".to_string(), "de".to_string()),
+    ];
+    expected.sort();
+    assert_eq!(get_cache_entries().unwrap(), expected);
+    clear_cache().unwrap();
+}
+
+#[test]
+fn prune_cache_by_age_test() {
+    let _lock = TEST_LOCK.lock().unwrap();
+    clear_cache().unwrap();
+    let text = "prune_cache_by_age_test".to_string();
+    into_cache_element(&text, "old", &None, "JA", "", 100).unwrap();
+    into_cache_element(&text, "new", &None, "DE", "", 100).unwrap();
+    // Make the JA entry 10 days old
+    let mut cache_data = get_cache_data().unwrap();
+    let key = cache_hash(&text, &None, "JA", "");
+    cache_data.elements.get_mut(&key).unwrap().created_at -= 10 * 24 * 60 * 60;
+    save_cache_data(cache_data).unwrap();
+
+    assert_eq!(prune_cache(&None, &None, Some(30 * 24 * 60 * 60)).unwrap(), 0);
+    assert_eq!(prune_cache(&None, &None, Some(7 * 24 * 60 * 60)).unwrap(), 1);
+    assert_eq!(search_cache(&text, &None, "JA", "").unwrap(), None);
+    assert_eq!(search_cache(&text, &None, "DE", "").unwrap(), Some("new".to_string()));
+    clear_cache().unwrap();
+}
@@ -3,6 +3,22 @@ use serde::{Deserialize, Serialize};
 use confy;
 use confy::ConfyError;
 use std::path::PathBuf;
+use std::collections::HashMap;
+use dptran::Formality;
+
+/// Configuration file name
+/// Tests use a separate file so as not to overwrite the user's settings.
+#[cfg(not(test))]
+const CONFIG_NAME: &str = "configure";
+#[cfg(test)]
+const CONFIG_NAME: &str = "configure_test";
+
+/// Lock for the tests sharing the configuration file
+#[cfg(test)]
+pub static TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Default target language
+pub const DEFAULT_TARGET_LANGUAGE: &str = "EN";
 
 /// Configure properties
 #[derive(Serialize, Deserialize, Debug)]
@@ -13,16 +29,49 @@ struct Configure {
     pub cache_max_entries: usize,
     pub editor_command: Option<String>,
     pub cache_enabled: bool,
+    #[serde(default)]
+    pub legacy_lang_expansion: bool,
+    #[serde(default)]
+    pub max_chars_per_run: usize,
+    #[serde(default)]
+    pub default_formality: Option<Formality>,
+    #[serde(default)]
+    pub audit_log_path: Option<String>,
+    #[serde(default = "default_cache_normalize_whitespace")]
+    pub cache_normalize_whitespace: bool,
+    #[serde(default)]
+    pub remember_last_langs: bool,
+    #[serde(default)]
+    pub last_source_lang: Option<String>,
+    #[serde(default)]
+    pub last_target_lang: Option<String>,
+    /// Glossary ID for each language pair ("SOURCE:TARGET")
+    #[serde(default)]
+    pub glossary_map: HashMap<String, String>,
+}
+
+/// Whitespace normalization of the cache keys is enabled by default (also for older configuration files).
+fn default_cache_normalize_whitespace() -> bool {
+    true
 }
 impl Default for Configure {
     fn default() -> Self {
         Self {
             settings_version: env!("CARGO_PKG_VERSION").to_string(),
             api_key: String::new(),
-            default_target_language: "EN".to_string(),
+            default_target_language: DEFAULT_TARGET_LANGUAGE.to_string(),
             cache_max_entries: 100,
             editor_command: None,
             cache_enabled: true,
+            legacy_lang_expansion: false,
+            max_chars_per_run: 0,
+            default_formality: None,
+            audit_log_path: None,
+            cache_normalize_whitespace: true,
+            remember_last_langs: false,
+            last_source_lang: None,
+            last_target_lang: None,
+            glossary_map: HashMap::new(),
         }
     }
 }
@@ -38,6 +87,16 @@ pub enum ConfigError {
     FailToClearSettings(String),
     FailToFixSettings,
     FailToSetCacheEnabled(String),
+    FailToSetLegacyLangExpansion(String),
+    FailToSetMaxCharsPerRun(String),
+    FailToSetDefaultFormality(String),
+    FailToSetAuditLogPath(String),
+    FailToSetCacheNormalizeWhitespace(String),
+    FailToSetRememberLastLangs(String),
+    FailToSetLastLangs(String),
+    FailToSetGlossaryFor(String),
+    FailToExportSettings(String),
+    FailToImportSettings(String),
 }
 impl fmt::Display for ConfigError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -50,6 +109,16 @@ impl fmt::Display for ConfigError {
             ConfigError::FailToClearSettings(ref e) => write!(f, "Failed to clear settings: {}", e),
             ConfigError::FailToFixSettings => write!(f, "Failed to fix settings"),
             ConfigError::FailToSetCacheEnabled(ref e) => write!(f, "Failed to set cache enabled: {}", e),
+            ConfigError::FailToSetLegacyLangExpansion(ref e) => write!(f, "Failed to set legacy language code expansion: {}", e),
+            ConfigError::FailToSetMaxCharsPerRun(ref e) => write!(f, "Failed to set max characters per run: {}", e),
+            ConfigError::FailToSetDefaultFormality(ref e) => write!(f, "Failed to set default formality: {}", e),
+            ConfigError::FailToSetAuditLogPath(ref e) => write!(f, "Failed to set audit log path: {}", e),
+            ConfigError::FailToSetCacheNormalizeWhitespace(ref e) => write!(f, "Failed to set cache whitespace normalization: {}", e),
+            ConfigError::FailToSetRememberLastLangs(ref e) => write!(f, "Failed to set remembering the last languages: {}", e),
+            ConfigError::FailToSetLastLangs(ref e) => write!(f, "Failed to set the last languages: {}", e),
+            ConfigError::FailToSetGlossaryFor(ref e) => write!(f, "Failed to set the glossary for the language pair: {}", e),
+            ConfigError::FailToExportSettings(ref e) => write!(f, "Failed to export settings: {}", e),
+            ConfigError::FailToImportSettings(ref e) => write!(f, "Failed to import settings: {}", e),
         }
     }
 }
@@ -58,7 +127,7 @@ impl fmt::Display for ConfigError {
 /// Get the API key and default target language for translation from the configuration file.
 /// If none exists, create a new one with a default value.
 fn get_settings() -> Result<Configure, ConfigError> {
-    let result = confy::load::<Configure>("dptran", "configure");
+    let result = confy::load::<Configure>("dptran", CONFIG_NAME);
     match result {
         Ok(settings) => Ok(settings),
         Err(e) => {
@@ -77,7 +146,7 @@ fn get_settings() -> Result<Configure, ConfigError> {
 pub fn set_api_key(api_key: String) -> Result<(), ConfigError> {
     let mut settings = get_settings()?;
     settings.api_key = api_key;
-    confy::store("dptran", "configure", settings).map_err(|e| ConfigError::FailToSetApiKey(e.to_string()))?;
+    confy::store("dptran", CONFIG_NAME, settings).map_err(|e| ConfigError::FailToSetApiKey(e.to_string()))?;
     Ok(())
 }
 
@@ -86,16 +155,22 @@ pub fn set_api_key(api_key: String) -> Result<(), ConfigError> {
 pub fn set_default_target_language(default_target_language: &String) -> Result<(), ConfigError> {
     let mut settings = get_settings()?;
     settings.default_target_language = default_target_language.to_string();
-    confy::store("dptran", "configure", settings).map_err(|e| ConfigError::FailToSetDefaultTargetLanguage(e.to_string()))?;
+    confy::store("dptran", CONFIG_NAME, settings).map_err(|e| ConfigError::FailToSetDefaultTargetLanguage(e.to_string()))?;
     Ok(())
 }
 
+/// Reset default destination language
+/// Restore the default target language for translation to the default value.
+pub fn reset_default_target_language() -> Result<(), ConfigError> {
+    set_default_target_language(&DEFAULT_TARGET_LANGUAGE.to_string())
+}
+
 /// Set cache maximum entries
 /// Set the maximum entries of the cache in the configuration file.
 pub fn set_cache_max_entries(cache_max_entries: usize) -> Result<(), ConfigError> {
     let mut settings = get_settings()?;
     settings.cache_max_entries = cache_max_entries;
-    confy::store("dptran", "configure", settings).map_err(|e| ConfigError::FailToSetCacheMaxEntries(e.to_string()))?;
+    confy::store("dptran", CONFIG_NAME, settings).map_err(|e| ConfigError::FailToSetCacheMaxEntries(e.to_string()))?;
     Ok(())
 }
 
@@ -103,7 +178,7 @@ pub fn set_cache_max_entries(cache_max_entries: usize) -> Result<(), ConfigError
 pub fn set_editor_command(editor_command: String) -> Result<(), ConfigError> {
     let mut settings = get_settings()?;
     settings.editor_command = Some(editor_command);
-    confy::store("dptran", "configure", settings).map_err(|e| ConfigError::FailToSetEditor(e.to_string()))?;
+    confy::store("dptran", CONFIG_NAME, settings).map_err(|e| ConfigError::FailToSetEditor(e.to_string()))?;
     Ok(())
 }
 
@@ -111,14 +186,126 @@ pub fn set_editor_command(editor_command: String) -> Result<(), ConfigError> {
 pub fn set_cache_enabled(cache_enabled: bool) -> Result<(), ConfigError> {
     let mut settings = get_settings()?;
     settings.cache_enabled = cache_enabled;
-    confy::store("dptran", "configure", settings).map_err(|e| ConfigError::FailToSetCacheEnabled(e.to_string()))?;
+    confy::store("dptran", CONFIG_NAME, settings).map_err(|e| ConfigError::FailToSetCacheEnabled(e.to_string()))?;
+    Ok(())
+}
+
+/// Set legacy language code expansion
+/// If enabled, EN and PT are converted to EN-US and PT-PT as before version 2.1.0.
+pub fn set_legacy_lang_expansion(legacy_lang_expansion: bool) -> Result<(), ConfigError> {
+    let mut settings = get_settings()?;
+    settings.legacy_lang_expansion = legacy_lang_expansion;
+    confy::store("dptran", CONFIG_NAME, settings).map_err(|e| ConfigError::FailToSetLegacyLangExpansion(e.to_string()))?;
+    Ok(())
+}
+
+/// Set maximum characters per run
+/// 0 means unlimited.
+pub fn set_max_chars_per_run(max_chars_per_run: usize) -> Result<(), ConfigError> {
+    let mut settings = get_settings()?;
+    settings.max_chars_per_run = max_chars_per_run;
+    confy::store("dptran", CONFIG_NAME, settings).map_err(|e| ConfigError::FailToSetMaxCharsPerRun(e.to_string()))?;
+    Ok(())
+}
+
+/// Set default formality
+/// Applied when `--formality` is not specified and the target language supports formality.
+pub fn set_default_formality(default_formality: Formality) -> Result<(), ConfigError> {
+    let mut settings = get_settings()?;
+    settings.default_formality = Some(default_formality);
+    confy::store("dptran", CONFIG_NAME, settings).map_err(|e| ConfigError::FailToSetDefaultFormality(e.to_string()))?;
+    Ok(())
+}
+
+/// Set audit log path
+/// None disables the audit log.
+pub fn set_audit_log_path(audit_log_path: Option<String>) -> Result<(), ConfigError> {
+    let mut settings = get_settings()?;
+    settings.audit_log_path = audit_log_path;
+    confy::store("dptran", CONFIG_NAME, settings).map_err(|e| ConfigError::FailToSetAuditLogPath(e.to_string()))?;
+    Ok(())
+}
+
+/// Set cache whitespace normalization
+/// If enabled, the cache keys ignore leading, trailing and repeated whitespace.
+pub fn set_cache_normalize_whitespace(cache_normalize_whitespace: bool) -> Result<(), ConfigError> {
+    let mut settings = get_settings()?;
+    settings.cache_normalize_whitespace = cache_normalize_whitespace;
+    confy::store("dptran", CONFIG_NAME, settings).map_err(|e| ConfigError::FailToSetCacheNormalizeWhitespace(e.to_string()))?;
+    Ok(())
+}
+
+/// Set remembering the last languages
+/// If enabled, the last target language is used when `-t` is not specified.
+pub fn set_remember_last_langs(remember_last_langs: bool) -> Result<(), ConfigError> {
+    let mut settings = get_settings()?;
+    settings.remember_last_langs = remember_last_langs;
+    confy::store("dptran", CONFIG_NAME, settings).map_err(|e| ConfigError::FailToSetRememberLastLangs(e.to_string()))?;
+    Ok(())
+}
+
+/// Set the languages of the last translation
+pub fn set_last_langs(last_source_lang: Option<String>, last_target_lang: String) -> Result<(), ConfigError> {
+    let mut settings = get_settings()?;
+    settings.last_source_lang = last_source_lang;
+    settings.last_target_lang = Some(last_target_lang);
+    confy::store("dptran", CONFIG_NAME, settings).map_err(|e| ConfigError::FailToSetLastLangs(e.to_string()))?;
     Ok(())
 }
 
+/// Set the glossary applied to the language pair
+/// If glossary_id is None, the glossary of the language pair is removed.
+pub fn set_glossary_for(source_lang: &str, target_lang: &str, glossary_id: Option<String>) -> Result<(), ConfigError> {
+    let mut settings = get_settings()?;
+    let pair = language_pair_key(source_lang, target_lang);
+    match glossary_id {
+        Some(glossary_id) => settings.glossary_map.insert(pair, glossary_id),
+        None => settings.glossary_map.remove(&pair),
+    };
+    confy::store("dptran", CONFIG_NAME, settings).map_err(|e| ConfigError::FailToSetGlossaryFor(e.to_string()))?;
+    Ok(())
+}
+
+/// Key of the language pair in the glossary map ("SOURCE:TARGET" in upper case)
+fn language_pair_key(source_lang: &str, target_lang: &str) -> String {
+    format!("{}:{}", source_lang.to_ascii_uppercase(), target_lang.to_ascii_uppercase())
+}
+
 /// Initialize settings
 pub fn clear_settings() -> Result<(), ConfigError> {
     let settings = Configure::default();
-    confy::store("dptran", "configure", settings).map_err(|e| ConfigError::FailToClearSettings(e.to_string()))?;
+    confy::store("dptran", CONFIG_NAME, settings).map_err(|e| ConfigError::FailToClearSettings(e.to_string()))?;
+    Ok(())
+}
+
+/// Export the settings to a JSON file to move them to another machine.
+/// The API key is excluded unless include_api_key is true.
+pub fn export_settings(path: &std::path::Path, include_api_key: bool) -> Result<(), ConfigError> {
+    let mut settings = get_settings()?;
+    if !include_api_key {
+        settings.api_key = String::new();
+    }
+    let json = serde_json::to_string_pretty(&settings).map_err(|e| ConfigError::FailToExportSettings(e.to_string()))?;
+    std::fs::write(path, json).map_err(|e| ConfigError::FailToExportSettings(e.to_string()))?;
+    Ok(())
+}
+
+/// Import the settings from a JSON file exported by ``export_settings()``.
+/// The current API key is kept if the file does not include one.
+pub fn import_settings(path: &std::path::Path) -> Result<(), ConfigError> {
+    let json = std::fs::read_to_string(path).map_err(|e| ConfigError::FailToImportSettings(e.to_string()))?;
+    let mut settings: Configure = serde_json::from_str(&json).map_err(|e| ConfigError::FailToImportSettings(e.to_string()))?;
+    if settings.default_target_language.trim().is_empty() {
+        return Err(ConfigError::FailToImportSettings("default_target_language is empty".to_string()));
+    }
+    if settings.cache_max_entries == 0 {
+        return Err(ConfigError::FailToImportSettings("cache_max_entries must be greater than 0".to_string()));
+    }
+    if settings.api_key.is_empty() {
+        settings.api_key = get_settings()?.api_key;
+    }
+    settings.settings_version = env!("CARGO_PKG_VERSION").to_string();
+    confy::store("dptran", CONFIG_NAME, settings).map_err(|e| ConfigError::FailToImportSettings(e.to_string()))?;
     Ok(())
 }
 
@@ -155,9 +342,73 @@ pub fn get_cache_enabled() -> Result<bool, ConfigError> {
     Ok(settings.cache_enabled)
 }
 
+/// Get legacy language code expansion
+pub fn get_legacy_lang_expansion() -> Result<bool, ConfigError> {
+    let settings = get_settings()?;
+    Ok(settings.legacy_lang_expansion)
+}
+
+/// Get maximum characters per run
+pub fn get_max_chars_per_run() -> Result<usize, ConfigError> {
+    let settings = get_settings()?;
+    Ok(settings.max_chars_per_run)
+}
+
+/// Get default formality
+pub fn get_default_formality() -> Result<Option<Formality>, ConfigError> {
+    let settings = get_settings()?;
+    Ok(settings.default_formality)
+}
+
+/// Get audit log path
+pub fn get_audit_log_path() -> Result<Option<String>, ConfigError> {
+    let settings = get_settings()?;
+    Ok(settings.audit_log_path)
+}
+
+/// Get cache whitespace normalization
+pub fn get_cache_normalize_whitespace() -> Result<bool, ConfigError> {
+    let settings = get_settings()?;
+    Ok(settings.cache_normalize_whitespace)
+}
+
+/// Get remembering the last languages
+pub fn get_remember_last_langs() -> Result<bool, ConfigError> {
+    let settings = get_settings()?;
+    Ok(settings.remember_last_langs)
+}
+
+/// Get the source and target languages of the last translation
+pub fn get_last_langs() -> Result<(Option<String>, Option<String>), ConfigError> {
+    let settings = get_settings()?;
+    Ok((settings.last_source_lang, settings.last_target_lang))
+}
+
+/// Get the target language used when `-t` is not specified.
+/// This is the last target language if remembering is enabled, otherwise the default target language.
+pub fn get_fallback_target_language_code() -> Result<String, ConfigError> {
+    let settings = get_settings()?;
+    match settings.last_target_lang {
+        Some(last_target_lang) if settings.remember_last_langs => Ok(last_target_lang),
+        _ => Ok(settings.default_target_language),
+    }
+}
+
+/// Get the glossary applied to the language pair
+pub fn get_glossary_for(source_lang: &str, target_lang: &str) -> Result<Option<String>, ConfigError> {
+    let settings = get_settings()?;
+    Ok(settings.glossary_map.get(&language_pair_key(source_lang, target_lang)).cloned())
+}
+
+/// Get the glossaries of all the language pairs
+pub fn get_glossary_map() -> Result<HashMap<String, String>, ConfigError> {
+    let settings = get_settings()?;
+    Ok(settings.glossary_map)
+}
+
 /// Get configuration file path
 pub fn get_config_file_path() -> Result<PathBuf, ConfigError> {
-    confy::get_configuration_file_path("dptran", "configure").map_err(|e| ConfigError::FailToGetSettings(e.to_string()))
+    confy::get_configuration_file_path("dptran", CONFIG_NAME).map_err(|e| ConfigError::FailToGetSettings(e.to_string()))
 }
 
 /// Configure properties
@@ -178,7 +429,7 @@ impl Default for ConfigureBeforeV200 {
 /// If the configuration file is older, update it.
 fn fix_settings() -> Result<Configure, ConfigError> {
     // from ver.2.0.0
-    let config_v2_0_0 = confy::load::<ConfigureBeforeV200>("dptran", "configure");
+    let config_v2_0_0 = confy::load::<ConfigureBeforeV200>("dptran", CONFIG_NAME);
     if config_v2_0_0.is_ok() {
         let config = config_v2_0_0.unwrap();
         let settings = Configure {
@@ -188,9 +439,123 @@ fn fix_settings() -> Result<Configure, ConfigError> {
             cache_max_entries: 100,
             editor_command: None,
             cache_enabled: true,
+            legacy_lang_expansion: false,
+            max_chars_per_run: 0,
+            default_formality: None,
+            audit_log_path: None,
+            cache_normalize_whitespace: true,
+            remember_last_langs: false,
+            last_source_lang: None,
+            last_target_lang: None,
+            glossary_map: HashMap::new(),
         };
-        confy::store("dptran", "configure", &settings).map_err(|e| ConfigError::FailToGetSettings(e.to_string()))?;
+        confy::store("dptran", CONFIG_NAME, &settings).map_err(|e| ConfigError::FailToGetSettings(e.to_string()))?;
         return Ok(settings);
     }
     Err(ConfigError::FailToFixSettings)
 }
+
+#[test]
+fn reset_default_target_language_test() {
+    let _lock = TEST_LOCK.lock().unwrap();
+    set_default_target_language(&"JA".to_string()).unwrap();
+    assert_eq!(get_default_target_language_code().unwrap(), "JA");
+    reset_default_target_language().unwrap();
+    assert_eq!(get_default_target_language_code().unwrap(), DEFAULT_TARGET_LANGUAGE);
+}
+
+#[test]
+fn default_formality_test() {
+    let _lock = TEST_LOCK.lock().unwrap();
+    set_default_formality(Formality::PreferLess).unwrap();
+    assert_eq!(get_default_formality().unwrap(), Some(Formality::PreferLess));
+    set_default_formality(Formality::More).unwrap();
+    assert_eq!(get_default_formality().unwrap(), Some(Formality::More));
+}
+
+#[test]
+fn audit_log_path_test() {
+    let _lock = TEST_LOCK.lock().unwrap();
+    set_audit_log_path(Some("/tmp/dptran_audit.log".to_string())).unwrap();
+    assert_eq!(get_audit_log_path().unwrap(), Some("/tmp/dptran_audit.log".to_string()));
+    set_audit_log_path(None).unwrap();
+    assert_eq!(get_audit_log_path().unwrap(), None);
+}
+
+#[test]
+fn cache_normalize_whitespace_test() {
+    let _lock = TEST_LOCK.lock().unwrap();
+    // Enabled if not in the configuration file
+    let settings: Configure = serde_json::from_str(r#"{"settings_version":"2.1.1","api_key":"","default_target_language":"EN","cache_max_entries":100,"editor_command":null,"cache_enabled":true}"#).unwrap();
+    assert!(settings.cache_normalize_whitespace);
+
+    set_cache_normalize_whitespace(false).unwrap();
+    assert!(!get_cache_normalize_whitespace().unwrap());
+    set_cache_normalize_whitespace(true).unwrap();
+    assert!(get_cache_normalize_whitespace().unwrap());
+}
+
+#[test]
+fn remember_last_langs_test() {
+    let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    clear_settings().unwrap();
+    set_last_langs(Some("EN".to_string()), "DE".to_string()).unwrap();
+    assert_eq!(get_last_langs().unwrap(), (Some("EN".to_string()), Some("DE".to_string())));
+
+    // Not remembered unless enabled
+    assert_eq!(get_fallback_target_language_code().unwrap(), DEFAULT_TARGET_LANGUAGE);
+
+    set_remember_last_langs(true).unwrap();
+    assert!(get_remember_last_langs().unwrap());
+    assert_eq!(get_fallback_target_language_code().unwrap(), "DE");
+
+    set_remember_last_langs(false).unwrap();
+    assert_eq!(get_fallback_target_language_code().unwrap(), DEFAULT_TARGET_LANGUAGE);
+    clear_settings().unwrap();
+}
+
+#[test]
+fn glossary_for_test() {
+    let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    clear_settings().unwrap();
+    set_glossary_for("en", "ja", Some("def3a26b-3e84-45b3-84ae-0c0aaf3525f7".to_string())).unwrap();
+    // Matched pair (case-insensitive)
+    assert_eq!(get_glossary_for("EN", "JA").unwrap(), Some("def3a26b-3e84-45b3-84ae-0c0aaf3525f7".to_string()));
+    // Unmatched pairs
+    assert_eq!(get_glossary_for("JA", "EN").unwrap(), None);
+    assert_eq!(get_glossary_for("EN", "DE").unwrap(), None);
+
+    set_glossary_for("EN", "JA", None).unwrap();
+    assert_eq!(get_glossary_for("EN", "JA").unwrap(), None);
+    clear_settings().unwrap();
+}
+
+#[test]
+fn export_import_settings_test() {
+    let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let path = std::env::temp_dir().join("dptran_export_import_settings_test.json");
+    clear_settings().unwrap();
+    set_api_key("dummy-key".to_string()).unwrap();
+    set_default_target_language(&"JA".to_string()).unwrap();
+
+    // The API key is excluded by default
+    export_settings(&path, false).unwrap();
+    assert!(!std::fs::read_to_string(&path).unwrap().contains("dummy-key"));
+    clear_settings().unwrap();
+    import_settings(&path).unwrap();
+    assert_eq!(get_default_target_language_code().unwrap(), "JA");
+    assert_eq!(get_api_key().unwrap(), None);
+
+    // With the API key
+    set_api_key("dummy-key".to_string()).unwrap();
+    export_settings(&path, true).unwrap();
+    clear_settings().unwrap();
+    import_settings(&path).unwrap();
+    assert_eq!(get_api_key().unwrap(), Some("dummy-key".to_string()));
+
+    // Invalid settings are rejected
+    std::fs::write(&path, "{}").unwrap();
+    assert!(import_settings(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+    clear_settings().unwrap();
+}
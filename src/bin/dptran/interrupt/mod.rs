@@ -0,0 +1,105 @@
+use std::io::{stdout, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use super::style;
+
+/// Exit code on the second Ctrl-C (128 + SIGINT).
+const EXIT_CODE_INTERRUPTED: i32 = 130;
+
+/// Number of Ctrl-C presses since the current input started.
+static INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+/// Whether the current input was cancelled by Ctrl-C.
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+/// Held while writing to the cache or the output file, so that Ctrl-C never exits in the middle of a write.
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+/// What to do on Ctrl-C.
+#[derive(Debug, PartialEq)]
+enum Action {
+    CancelInput,
+    Exit,
+}
+
+/// Install the Ctrl-C handler for the interactive mode.
+/// The first Ctrl-C cancels the current input, and the second one exits after the pending writes.
+pub fn install() -> Result<(), ctrlc::Error> {
+    ctrlc::set_handler(|| match on_interrupt() {
+        Action::CancelInput => {
+            println!();
+            println!("Input cancelled. Type \"quit\" to exit dptran, or press Ctrl-C again to exit now.");
+            print!("{}", style::prompt("> "));
+            let _ = stdout().flush();
+        }
+        Action::Exit => {
+            wait_for_writes();
+            println!();
+            std::process::exit(EXIT_CODE_INTERRUPTED);
+        }
+    })
+}
+
+/// Count the Ctrl-C press.
+fn on_interrupt() -> Action {
+    if INTERRUPTS.fetch_add(1, Ordering::SeqCst) == 0 {
+        CANCELLED.store(true, Ordering::SeqCst);
+        Action::CancelInput
+    } else {
+        Action::Exit
+    }
+}
+
+/// Start a new input: Ctrl-C pressed before is forgotten.
+pub fn reset() {
+    INTERRUPTS.store(0, Ordering::SeqCst);
+    CANCELLED.store(false, Ordering::SeqCst);
+}
+
+/// Whether the current input was cancelled. The flag is cleared.
+pub fn take_cancelled() -> bool {
+    CANCELLED.swap(false, Ordering::SeqCst)
+}
+
+/// Hold it while writing to the cache or the output file.
+pub fn write_guard() -> MutexGuard<'static, ()> {
+    WRITE_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Wait until the writes in progress are completed.
+fn wait_for_writes() {
+    drop(write_guard());
+}
+
+#[test]
+fn interrupt_test() {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    // First Ctrl-C cancels the input, second one exits
+    reset();
+    assert_eq!(on_interrupt(), Action::CancelInput);
+    assert!(take_cancelled());
+    assert!(!take_cancelled());
+    assert_eq!(on_interrupt(), Action::Exit);
+    // A new input starts over
+    reset();
+    assert_eq!(on_interrupt(), Action::CancelInput);
+    reset();
+
+    // The exit waits for the write in progress
+    let written = Arc::new(AtomicBool::new(false));
+    let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+    let writer = {
+        let written = Arc::clone(&written);
+        std::thread::spawn(move || {
+            let _guard = write_guard();
+            locked_tx.send(()).unwrap();
+            std::thread::sleep(Duration::from_millis(100));
+            written.store(true, Ordering::SeqCst);
+        })
+    };
+    locked_rx.recv().unwrap();
+    wait_for_writes();
+    assert!(written.load(Ordering::SeqCst));
+    writer.join().unwrap();
+}
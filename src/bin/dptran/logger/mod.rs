@@ -0,0 +1,29 @@
+use log::{Log, Metadata, Record, LevelFilter};
+
+/// Logger printing the log messages to stderr.
+/// Enabled by `--verbose`.
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.level().as_str().to_ascii_lowercase(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Initialize the logger.
+/// If verbose is true, debug messages are printed. Otherwise, nothing is printed.
+pub fn init(verbose: bool) {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(if verbose { LevelFilter::Debug } else { LevelFilter::Off });
+    }
+}
@@ -1,15 +1,71 @@
-use std::io::{self, Write, stdin, stdout, BufWriter};
+use std::io::{self, Write, BufRead, stdin, stdout, BufWriter};
 use std::fs::OpenOptions;
 use std::fmt::Debug;
 
 mod parse;
 mod configure;
 mod cache;
+mod logger;
+mod audit;
+mod style;
+mod interrupt;
 
-use dptran::{DpTranError, DpTranUsage, LangType};
+use dptran::{DpTranError, DpTranUsage, DpTranTranslation, LangType, LangCodeName, TranslateOptions, Formality, SplitSentences};
 use configure::ConfigError;
 use cache::CacheError;
 use parse::ExecutionMode;
+use serde::Serialize;
+
+/// Default threshold (characters) of the warning for short inputs with auto-detection.
+const DEFAULT_SOURCE_LANG_DETECT_THRESHOLD: usize = 10;
+
+/// Hint for 403 Forbidden: DeepL may deactivate free API keys that have not been used for a long time.
+const DORMANT_KEY_HINT: &str = "Hint: If the API key has not been used for a long time, it may have been deactivated. Check and reactivate it at https://www.deepl.com/your-account/keys";
+
+/// Handling of the line breaks in the input.  
+/// ``Keep``: Translate line by line  
+/// ``Remove``: Join all lines into one (`--remove-line-breaks`)  
+/// ``Reflow``: Join the lines in each paragraph (`--reflow`)  
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LineBreaks {
+    Keep,
+    Remove,
+    Reflow,
+}
+
+/// Clean-up of the input lines.  
+/// ``trim``: Strip leading and trailing whitespace from each line (`--trim`)  
+/// ``collapse_blank_lines``: Remove blank lines (`--collapse-blank-lines`)  
+#[derive(Debug, Clone, Copy, Default)]
+struct InputFilter {
+    trim: bool,
+    collapse_blank_lines: bool,
+}
+
+/// Options of a translation run.  
+/// ``multilines``: Send the input in interactive mode with a blank line (`--multilines`)  
+/// ``once``: Translate only one input in interactive mode (`--interactive-once`)  
+/// ``line_breaks``: Handling of the line breaks  
+/// ``input_filter``: Clean-up of the input lines  
+/// ``keep_going``: Output the partial results on failure (`--keep-going`)  
+/// ``skip_detected``: Print the lines detected as this language unchanged (`--skip-same` / `--skip-if-detected`)  
+/// ``show_source``: Put each source line above its translation (`--show-source`)  
+/// ``cache_override``: Use the cache or not regardless of the settings (`--cache` / `--no-cache`)  
+/// ``max_chars``: Maximum characters sent in the run (0: unlimited)  
+/// ``detect_threshold``: Threshold (characters) of the warning for short inputs with auto-detection  
+#[derive(Debug, Clone)]
+struct RunOptions {
+    multilines: bool,
+    once: bool,
+    line_breaks: LineBreaks,
+    input_filter: InputFilter,
+    keep_going: bool,
+    skip_detected: Option<String>,
+    show_source: bool,
+    cache_override: Option<bool>,
+    max_chars: usize,
+    detect_threshold: usize,
+}
 
 enum RuntimeError {
     DeeplApiError(dptran::DpTranError),
@@ -18,6 +74,8 @@ enum RuntimeError {
     FileIoError(String),
     EditorError(String),
     CacheError(CacheError),
+    MaxCharsExceeded(usize, usize),
+    FormalityNotSupported(String, Vec<String>),
 }
 impl ToString for RuntimeError {
     fn to_string(&self) -> String {
@@ -28,14 +86,20 @@ impl ToString for RuntimeError {
                         match e {
                             dptran::DeeplAPIError::ConnectionError(e) => {
                                 match e {
-                                    dptran::ConnectionError::Forbidden => "403 Forbidden Error. Maybe the API key is invalid.".to_string(),
+                                    dptran::ConnectionError::Forbidden => format!("403 Forbidden Error. Maybe the API key is invalid.\n{}", DORMANT_KEY_HINT),
                                     dptran::ConnectionError::NotFound => "404 Not Found Error. Make sure the internet connection is working.".to_string(),
+                                    dptran::ConnectionError::Offline(e) => format!("No network connection. Make sure the internet connection is working. ({})", e),
+                                    dptran::ConnectionError::Timeout(e) => format!("The request to DeepL API timed out. Try again later or increase the timeout with --timeout. ({})", e),
+                                    dptran::ConnectionError::TooManyRequests => "429 Too Many Requests. Try again later or retry automatically with --max-retries.".to_string(),
+                                    dptran::ConnectionError::ServiceUnavailable => "DeepL service temporarily unavailable (503). It may be under maintenance. Try again later or retry automatically with --max-retries.".to_string(),
                                     e => format!("Connection error: {}", e),
                                 }
                             },
                             e => format!("Deepl API error: {}", e.to_string()),
                         }
                     },
+                    dptran::DpTranError::GlossaryRequiresSourceLang => "A glossary requires the source language. Specify it with --from.".to_string(),
+                    dptran::DpTranError::RequestTooLarge(size, limit) => format!("The request ({} bytes, URL-encoded) exceeds the limit of DeepL API ({} bytes per request). Split the input into smaller parts or translate it line by line with --stream.", size, limit),
                     e => format!("Deepl API error: {}", e.to_string()),
                 }
             }
@@ -44,9 +108,44 @@ impl ToString for RuntimeError {
             RuntimeError::FileIoError(e) => format!("File I/O error: {}", e),
             RuntimeError::EditorError(e) => format!("Editor error: {}", e),
             RuntimeError::CacheError(e) => format!("Cache error: {}", e),
+            RuntimeError::FormalityNotSupported(target_lang, supported_langs) => format!("The target language {} does not support formality. Supported languages: {}\nUse prefer_more / prefer_less or --formality-fallback to fall back to the default formality.", target_lang, supported_langs.join(", ")),
+            RuntimeError::MaxCharsExceeded(requested, allowed) => format!("The input has {} characters in total, but only {} characters are allowed per run. Use --max-chars to change the limit.", requested, allowed),
         }
     }
 }
+impl RuntimeError {
+    /// Process exit code for the error.  
+    /// 1: Other errors  
+    /// 2: Invalid arguments (returned by clap)  
+    /// 3: API key is not set  
+    /// 4: Connection error  
+    /// 5: Translation limit reached  
+    /// 6: Configuration error  
+    fn exit_code(&self) -> i32 {
+        match self {
+            RuntimeError::DeeplApiError(DpTranError::ApiKeyIsNotSet) => 3,
+            RuntimeError::DeeplApiError(DpTranError::DeeplApiError(dptran::DeeplAPIError::LimitError)) => 5,
+            RuntimeError::DeeplApiError(DpTranError::DeeplApiError(dptran::DeeplAPIError::ConnectionError(_))) => 4,
+            RuntimeError::ConfigError(_) => 6,
+            _ => 1,
+        }
+    }
+}
+impl From<DpTranError> for RuntimeError {
+    fn from(e: DpTranError) -> Self {
+        RuntimeError::DeeplApiError(e)
+    }
+}
+impl From<ConfigError> for RuntimeError {
+    fn from(e: ConfigError) -> Self {
+        RuntimeError::ConfigError(e)
+    }
+}
+impl From<CacheError> for RuntimeError {
+    fn from(e: CacheError) -> Self {
+        RuntimeError::CacheError(e)
+    }
+}
 impl Debug for RuntimeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.to_string())
@@ -59,7 +158,7 @@ impl Debug for RuntimeError {
 fn get_usage() -> Result<DpTranUsage, RuntimeError> {
     let api_key = get_api_key()?;
     if let Some(api_key) = api_key {
-        dptran::get_usage(&api_key).map_err(|e| RuntimeError::DeeplApiError(e))
+        Ok(dptran::get_usage(&api_key)?)
     } else {
         Err(RuntimeError::DeeplApiError(DpTranError::ApiKeyIsNotSet))
     }
@@ -68,20 +167,37 @@ fn get_usage() -> Result<DpTranUsage, RuntimeError> {
 /// Display the number of characters remaining.
 fn show_usage() -> Result<(), RuntimeError> {
     let usage = get_usage()?;
-    if usage.unlimited {
-        println!("usage: {} / unlimited", usage.character_count);
+    print!("{}", format_usage(&usage));
+    Ok(())
+}
+
+/// Format the usage for display.
+fn format_usage(usage: &DpTranUsage) -> String {
+    let mut s = String::new();
+    s.push_str(&format!("usage: {}\n", usage));
+    if !usage.unlimited {
+        s.push_str(&format!("remaining: {}\n", usage.character_limit - usage.character_count));
     }
-    else {
-        println!("usage: {} / {} ({}%)", usage.character_count, usage.character_limit, (usage.character_count as f64 / usage.character_limit as f64 * 100.0).round());
-        println!("remaining: {}", usage.character_limit - usage.character_count);
+    // Document usage is only available for some plans
+    if let (Some(document_count), Some(document_limit)) = (usage.document_count, usage.document_limit) {
+        s.push_str(&format!("document usage: {} / {}\n", document_count, document_limit));
     }
-    Ok(())
+    s
+}
+
+/// Build information for bug reports.
+/// The values are set by build.rs.
+fn build_info() -> String {
+    let features = env!("DPTRAN_FEATURES");
+    format!("dptran {}\ntarget: {}\nrustc: {}\nfeatures: {}\n",
+        env!("CARGO_PKG_VERSION"), env!("DPTRAN_BUILD_TARGET"), env!("DPTRAN_RUSTC_VERSION"),
+        if features.is_empty() { "none" } else { features })
 }
 
 /// Set API key (using confy crate).
 /// Set the API key in the configuration file config.json.
 fn set_api_key(api_key: String) -> Result<(), RuntimeError> {
-    configure::set_api_key(api_key).map_err(|e| RuntimeError::ConfigError(e))?;
+    configure::set_api_key(api_key)?;
     Ok(())
 }
 
@@ -94,18 +210,66 @@ fn set_default_target_language(arg_default_target_language: String) -> Result<()
     };
 
     // Check if the language code is correct
-    if let Ok(validated_language_code) = dptran::correct_target_language_code(&api_key, &arg_default_target_language) {
-        configure::set_default_target_language(&validated_language_code).map_err(|e| RuntimeError::ConfigError(e))?;
-        println!("Default target language has been set to {}.", validated_language_code);
-        Ok(())
+    let validated_language_code = correct_language_code(&api_key, &arg_default_target_language, LangType::Target)?;
+    configure::set_default_target_language(&validated_language_code)?;
+    println!("Default target language has been set to {}.", validated_language_code);
+    Ok(())
+}
+
+/// Convert to correct language code.
+/// If the language code is invalid, suggest the closest valid one.
+fn correct_language_code(api_key: &String, language_code: &str, lang_type: LangType) -> Result<String, RuntimeError> {
+    let result = match lang_type {
+        LangType::Source => dptran::correct_source_language_code(api_key, language_code),
+        LangType::Target => {
+            let legacy_lang_expansion = configure::get_legacy_lang_expansion()?;
+            let language_code = expand_target_language_code(language_code, legacy_lang_expansion);
+            dptran::correct_target_language_code(api_key, &language_code)
+        }
+    };
+    match result {
+        Ok(validated_language_code) => Ok(validated_language_code),
+        Err(DpTranError::InvalidLanguageCode) => {
+            if let Ok(Some(suggestion)) = dptran::suggest_language_code(api_key, language_code, lang_type) {
+                eprintln!("Unknown language '{}'. Did you mean '{}'?", language_code, suggestion);
+            }
+            Err(RuntimeError::DeeplApiError(DpTranError::InvalidLanguageCode))
+        }
+        Err(e) => Err(RuntimeError::DeeplApiError(e)),
+    }
+}
+
+/// Reset default destination language.
+/// Restore the default target language for translation to EN.
+fn reset_default_target_language() -> Result<(), RuntimeError> {
+    configure::reset_default_target_language()?;
+    println!("Default target language has been reset to {}.", configure::DEFAULT_TARGET_LANGUAGE);
+    Ok(())
+}
+
+/// Expand EN and PT to EN-US and PT-PT if the legacy language code expansion is enabled.
+fn expand_target_language_code(language_code: &str, legacy_lang_expansion: bool) -> String {
+    if legacy_lang_expansion {
+        dptran::expand_legacy_language_code(language_code)
     } else {
-        Err(RuntimeError::DeeplApiError(DpTranError::InvalidLanguageCode))
+        language_code.to_string()
     }
 }
 
 /// Set the editor command.
 fn set_editor_command(editor_command: String) -> Result<(), RuntimeError> {
-    configure::set_editor_command(editor_command).map_err(|e| RuntimeError::ConfigError(e))?;
+    configure::set_editor_command(editor_command)?;
+    Ok(())
+}
+
+/// Set the legacy language code expansion.
+fn set_legacy_lang_expansion(legacy_lang_expansion: bool) -> Result<(), RuntimeError> {
+    configure::set_legacy_lang_expansion(legacy_lang_expansion)?;
+    if legacy_lang_expansion {
+        println!("EN and PT will be converted to EN-US and PT-PT.");
+    } else {
+        println!("EN and PT will be used as they are.");
+    }
     Ok(())
 }
 
@@ -116,8 +280,8 @@ fn clear_settings() -> Result<(), RuntimeError> {
     let mut input = String::new();
     io::stdin().read_line(&mut input).unwrap();
     // Initialize settings when y is entered.
-    if input.trim().to_ascii_lowercase() == "y" {
-        configure::clear_settings().map_err(|e| RuntimeError::ConfigError(e))?;
+    if input.trim().eq_ignore_ascii_case("y") {
+        configure::clear_settings()?;
         println!("All settings have been cleared.");
         println!("Note: You need to set the API key again to use dptran.");
     }
@@ -126,41 +290,94 @@ fn clear_settings() -> Result<(), RuntimeError> {
 
 /// Get the configured default destination language code.
 fn get_default_target_language_code() -> Result<String, RuntimeError> {
-    let default_target_lang = configure::get_default_target_language_code().map_err(|e| RuntimeError::ConfigError(e))?;
+    let default_target_lang = configure::get_default_target_language_code()?;
     Ok(default_target_lang)
 }
 
+/// Environment variable overriding the configured default target language.
+const DEFAULT_TARGET_LANG_ENV: &str = "DPTRAN_DEFAULT_TARGET_LANG";
+
+/// Select the target language.
+/// Precedence: `-t` option > `DPTRAN_DEFAULT_TARGET_LANG` > default_target_lang
+/// default_target_lang is the last target language if remembered, otherwise the configured default target language (see `configure::get_fallback_target_language_code`).
+fn select_target_language(arg_target_lang: Option<String>, env_target_lang: Option<String>, default_target_lang: String) -> String {
+    if let Some(target_lang) = arg_target_lang {
+        return target_lang;
+    }
+    match env_target_lang {
+        Some(target_lang) if !target_lang.trim().is_empty() => target_lang.trim().to_string(),
+        _ => default_target_lang,
+    }
+}
+
 /// Load the API key from the configuration file.
 fn get_api_key() -> Result<Option<String>, RuntimeError> {
-    let api_key = configure::get_api_key().map_err(|e| RuntimeError::ConfigError(e))?;
+    let api_key = configure::get_api_key()?;
     Ok(api_key)
 }
 
 /// Get the maximum number of cache entries.
 fn get_cache_max_entries() -> Result<usize, RuntimeError> {
-    let cache_max_entries = configure::get_cache_max_entries().map_err(|e| RuntimeError::ConfigError(e))?;
+    let cache_max_entries = configure::get_cache_max_entries()?;
     Ok(cache_max_entries)
 }
 
 /// Load the editor command from the configuration file.
 fn get_editor_command_str() -> Result<Option<String>, RuntimeError> {
-    let editor_command = configure::get_editor_command().map_err(|e| RuntimeError::ConfigError(e))?;
+    let editor_command = configure::get_editor_command()?;
     Ok(editor_command)
 }
 
 /// Get the cache enabled status.
 fn get_cache_enabled() -> Result<bool, RuntimeError> {
-    let cache_enabled = configure::get_cache_enabled().map_err(|e| RuntimeError::ConfigError(e))?;
+    let cache_enabled = configure::get_cache_enabled()?;
     Ok(cache_enabled)
 }
 
+/// Settings shown by `dptran set --show --json`.
+/// The API key is not included.
+#[derive(Serialize)]
+struct Settings {
+    default_target_language: String,
+    cache_max_entries: usize,
+    cache_enabled: bool,
+    editor_command: Option<String>,
+    config_file_path: String,
+}
+
+/// Collect the settings for `dptran set --show --json`.
+fn get_settings() -> Result<Settings, RuntimeError> {
+    Ok(Settings {
+        default_target_language: get_default_target_language_code()?,
+        cache_max_entries: get_cache_max_entries()?,
+        cache_enabled: get_cache_enabled()?,
+        editor_command: get_editor_command_str()?,
+        config_file_path: configure::get_config_file_path()?.to_string_lossy().to_string(),
+    })
+}
+
 /// Display of settings.
-fn display_settings() -> Result<(), RuntimeError> {
+/// If json is true, the settings are printed in JSON format.
+fn display_settings(json: bool) -> Result<(), RuntimeError> {
+    if json {
+        let settings = get_settings()?;
+        println!("{}", serde_json::to_string_pretty(&settings).map_err(|e| RuntimeError::StdIoError(e.to_string()))?);
+        return Ok(());
+    }
+
     let api_key = get_api_key()?;
     let default_target_lang = get_default_target_language_code()?;
     let cache_max_entries = get_cache_max_entries()?;
     let editor_command = get_editor_command_str()?;
     let cache_enabled = get_cache_enabled()?;
+    let cache_normalize_whitespace = configure::get_cache_normalize_whitespace()?;
+    let legacy_lang_expansion = configure::get_legacy_lang_expansion()?;
+    let max_chars_per_run = configure::get_max_chars_per_run()?;
+    let default_formality = configure::get_default_formality()?;
+    let audit_log_path = configure::get_audit_log_path()?;
+    let remember_last_langs = configure::get_remember_last_langs()?;
+    let last_langs = configure::get_last_langs()?;
+    let glossary_map = configure::get_glossary_map()?;
 
     if let Some(api_key) = api_key {
         println!("API key: {}", api_key);
@@ -182,81 +399,346 @@ fn display_settings() -> Result<(), RuntimeError> {
 
     println!("Cache enabled: {}", cache_enabled);
 
-    let config_filepath = configure::get_config_file_path().map_err(|e| RuntimeError::ConfigError(e))?;
+    println!("Cache whitespace normalization: {}", cache_normalize_whitespace);
+
+    println!("Remember last languages: {}", remember_last_langs);
+    if let (last_source_lang, Some(last_target_lang)) = last_langs {
+        println!("Last languages: {} -> {}", last_source_lang.unwrap_or("auto".to_string()), last_target_lang);
+    }
+
+    let mut glossary_pairs = glossary_map.into_iter().collect::<Vec<(String, String)>>();
+    glossary_pairs.sort();
+    for (pair, glossary_id) in glossary_pairs {
+        println!("Glossary for {}: {}", pair, glossary_id);
+    }
+
+    println!("Legacy language code expansion: {}", legacy_lang_expansion);
+
+    if max_chars_per_run > 0 {
+        println!("Max characters per run: {}", max_chars_per_run);
+    }
+    else {
+        println!("Max characters per run: unlimited");
+    }
+
+    println!("Default formality: {}", default_formality.unwrap_or(Formality::Default));
+
+    println!("Audit log: {}", audit_log_path.as_deref().unwrap_or("disabled"));
+
+    let config_filepath = configure::get_config_file_path()?;
     println!("Configuration file path: {}", config_filepath.to_str().unwrap());
 
     Ok(())
 }
 
-/// Display list of source language codes.
-/// Retrieved from <https://api-free.deepl.com/v2/languages>
-fn show_source_language_codes() -> Result<(), RuntimeError> {
-    let api_key = match get_api_key()? {
-        Some(api_key) => api_key,
-        None => return Err(RuntimeError::DeeplApiError(DpTranError::ApiKeyIsNotSet)),
-    };
+/// Result of a check by `dptran doctor`.
+struct DoctorCheck {
+    name: &'static str,
+    passed: bool,
+    detail: String,
+}
+impl DoctorCheck {
+    fn new(name: &'static str, result: Result<String, String>) -> Self {
+        match result {
+            Ok(detail) => Self { name, passed: true, detail },
+            Err(detail) => Self { name, passed: false, detail },
+        }
+    }
+}
 
-    // List of source language codes.
-    let source_lang_codes = dptran::get_language_codes(&api_key, LangType::Source).map_err(|e| RuntimeError::DeeplApiError(e))?;
-    
-    let mut i = 0;
-    let (len, max_code_len, max_str_len) = get_langcodes_maxlen(&source_lang_codes);
+/// Check the API key, the connection to DeepL API and the settings files.
+fn doctor_checks(api_key: &Option<String>) -> Vec<DoctorCheck> {
+    let mut checks = Vec::new();
 
-    println!("Source language codes:");
-    for lang_code in source_lang_codes {
-        print!(" {lc:<cl$}: {ls:<sl$}", lc=lang_code.0.trim_matches('"'), ls=lang_code.1.trim_matches('"'), cl=max_code_len, sl=max_str_len);
-        i += 1;
-        if (i % 3) == 0 || i == len {
-            println!();
+    // API key presence
+    checks.push(DoctorCheck::new("API key is set", match api_key {
+        Some(_) => Ok("set".to_string()),
+        None => Err("not set. Set it by `dptran set --api-key <API_KEY>`".to_string()),
+    }));
+
+    // Reachability of the endpoints in use (including the overridden ones)
+    // Only an HTTP response counts as reachable: TLS errors and timeouts are failures
+    let endpoints: [(&'static str, String); 3] = [
+        ("Translate endpoint is reachable", dptran::translate_endpoint()),
+        ("Usage endpoint is reachable", dptran::usage_endpoint()),
+        ("Languages endpoint is reachable", dptran::languages_endpoint()),
+    ];
+    for (name, url) in endpoints {
+        checks.push(DoctorCheck::new(name, match dptran::check_endpoint(&url) {
+            Ok(status) => Ok(format!("{} (HTTP {})", url, status)),
+            Err(e) => Err(format!("{}: {}", url, RuntimeError::DeeplApiError(e).to_string())),
+        }));
+    }
+
+    // API key validity
+    checks.push(DoctorCheck::new("API key is valid", match api_key {
+        Some(api_key) => match dptran::get_usage(api_key) {
+            Ok(_) => Ok("valid".to_string()),
+            Err(e) => Err(RuntimeError::DeeplApiError(e).to_string()),
+        },
+        None => Err("skipped (API key is not set)".to_string()),
+    }));
+
+    // Settings files writability
+    let config_filepath = configure::get_config_file_path().map_err(|e| e.to_string());
+    checks.push(DoctorCheck::new("Configuration file is writable", config_filepath.and_then(|path| check_writable(&path))));
+    let cache_filepath = cache::get_cache_file_path().map_err(|e| e.to_string());
+    checks.push(DoctorCheck::new("Cache file is writable", cache_filepath.and_then(|path| check_writable(&path))));
+
+    checks
+}
+
+/// Check if the file can be written without modifying it.
+/// If the file does not exist, check the permissions of the nearest existing directory, without creating anything.
+fn check_writable(path: &std::path::Path) -> Result<String, String> {
+    if path.exists() {
+        OpenOptions::new().append(true).open(path).map_err(|e| e.to_string())?;
+        return Ok(path.display().to_string());
+    }
+    let dir = path.ancestors().skip(1).find(|dir| dir.exists()).ok_or("Invalid path".to_string())?;
+    let metadata = std::fs::metadata(dir).map_err(|e| e.to_string())?;
+    if !metadata.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()));
+    }
+    if metadata.permissions().readonly() {
+        return Err(format!("{} is read-only", dir.display()));
+    }
+    Ok(format!("{} (will be created)", path.display()))
+}
+
+/// Display the results of `dptran doctor`.
+/// The failed checks are reported, but it is not an error.
+fn doctor() -> Result<(), RuntimeError> {
+    let api_key = get_api_key()?;
+    for check in doctor_checks(&api_key) {
+        println!("[{}] {}: {}", if check.passed { " OK " } else { "FAIL" }, check.name, check.detail);
+    }
+    Ok(())
+}
+
+/// Check that the API key and DeepL API are working for `dptran ping`.
+/// The failure is returned as an error, so that the exit code tells the cause.
+fn ping() -> Result<(), RuntimeError> {
+    let api_key = get_api_key()?.unwrap_or_default();
+    dptran::health_check(&api_key)?;
+    println!("OK");
+    Ok(())
+}
+
+/// Information shown by `dptran info`.
+/// The API key itself is not included.
+#[derive(Serialize)]
+struct Info {
+    version: String,
+    api_key: String,
+    config_file_path: String,
+    cache_enabled: bool,
+    cache_entries: usize,
+    cache_max_entries: usize,
+    cache_file_path: String,
+    endpoints: Endpoints,
+}
+
+/// DeepL API endpoints used by dptran.
+#[derive(Serialize)]
+struct Endpoints {
+    translate: String,
+    usage: String,
+    languages: String,
+}
+
+/// Collect the information for `dptran info`.
+fn get_info(api_key: &Option<String>) -> Result<Info, RuntimeError> {
+    Ok(Info {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        api_key: match api_key {
+            Some(api_key) if api_key.ends_with(":fx") => "set (free)",
+            Some(_) => "set (pro)",
+            None => "not set",
+        }.to_string(),
+        config_file_path: configure::get_config_file_path()?.display().to_string(),
+        cache_enabled: get_cache_enabled()?,
+        cache_entries: cache::get_cache_entries_count()?,
+        cache_max_entries: get_cache_max_entries()?,
+        cache_file_path: cache::get_cache_file_path()?.display().to_string(),
+        endpoints: Endpoints {
+            translate: dptran::translate_endpoint(),
+            usage: dptran::usage_endpoint(),
+            languages: dptran::languages_endpoint(),
+        },
+    })
+}
+
+/// Lines of `dptran info`.
+fn info_lines(info: &Info) -> Vec<String> {
+    vec![
+        format!("dptran version: {}", info.version),
+        format!("API key: {}", info.api_key),
+        format!("Configuration file path: {}", info.config_file_path),
+        format!("Cache: {} ({} / {} entries)", if info.cache_enabled { "enabled" } else { "disabled" }, info.cache_entries, info.cache_max_entries),
+        format!("Cache file path: {}", info.cache_file_path),
+        format!("Endpoint (translate): {}", info.endpoints.translate),
+        format!("Endpoint (usage): {}", info.endpoints.usage),
+        format!("Endpoint (languages): {}", info.endpoints.languages),
+    ]
+}
+
+/// Display the information for bug reports.
+/// If json is true, it is displayed as a JSON object.
+fn info(json: bool) -> Result<(), RuntimeError> {
+    let api_key = get_api_key()?;
+    let info = get_info(&api_key)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&info).map_err(|e| RuntimeError::StdIoError(e.to_string()))?);
+    } else {
+        for line in info_lines(&info) {
+            println!("{}", line);
         }
     }
+    Ok(())
+}
 
+/// Display list of source language codes.
+/// Retrieved from <https://api-free.deepl.com/v2/languages>
+fn show_source_language_codes() -> Result<(), RuntimeError> {
+    let source_lang_codes = get_language_codes(LangType::Source)?;
+    print!("{}", format_source_language_codes(&source_lang_codes));
     Ok(())
 }
 /// Display of list of language codes to be translated.
+/// Languages supporting formality are marked with `*`.
 fn show_target_language_codes() -> Result<(), RuntimeError> {
+    let target_lang_codes = get_target_language_codes()?;
+    print!("{}", format_target_language_codes(&target_lang_codes));
+    Ok(())
+}
+/// Display both lists of source and target language codes.
+fn show_all_language_codes() -> Result<(), RuntimeError> {
+    let source_lang_codes = get_language_codes(LangType::Source)?;
+    let target_lang_codes = get_target_language_codes()?;
+    print!("{}", format_all_language_codes(&source_lang_codes, &target_lang_codes));
+    Ok(())
+}
+/// Get list of language codes.
+fn get_language_codes(lang_type: LangType) -> Result<Vec<LangCodeName>, RuntimeError> {
     let api_key = match get_api_key()? {
         Some(api_key) => api_key,
         None => return Err(RuntimeError::DeeplApiError(DpTranError::ApiKeyIsNotSet)),
     };
-
-    // List of Language Codes.
-    let target_lang_codes = dptran::get_language_codes(&api_key, LangType::Target).map_err(|e| RuntimeError::DeeplApiError(e))?;
-
+    let lang_codes = fetch_language_codes(&api_key, lang_type)?;
+    Ok(lang_codes.into_iter().map(|(lang_code, _)| lang_code).collect())
+}
+/// Get list of target language codes with formality support.
+fn get_target_language_codes() -> Result<Vec<(LangCodeName, bool)>, RuntimeError> {
+    let api_key = match get_api_key()? {
+        Some(api_key) => api_key,
+        None => return Err(RuntimeError::DeeplApiError(DpTranError::ApiKeyIsNotSet)),
+    };
+    fetch_language_codes(&api_key, LangType::Target)
+}
+/// Get list of language codes with formality support.
+/// If the cache is enabled, the list is kept in the cache with its ETag and is not downloaded again while unchanged.
+fn fetch_language_codes(api_key: &String, lang_type: LangType) -> Result<Vec<(LangCodeName, bool)>, RuntimeError> {
+    if !get_cache_enabled()? {
+        return Ok(dptran::get_language_codes_with_formality(api_key, lang_type)?);
+    }
+    let type_name = match lang_type {
+        LangType::Source => "source",
+        LangType::Target => "target",
+    };
+    let cached = cache::get_cached_language_codes(type_name)?;
+    let lang_codes = dptran::get_language_codes_with_etag(api_key, lang_type, cached.clone())?;
+    if cached.as_ref() == Some(&lang_codes) {
+        log::debug!("{} language codes not modified", type_name);
+    } else {
+        cache::store_language_codes(type_name, &lang_codes)?;
+    }
+    Ok(lang_codes.lang_codes)
+}
+/// Get the target language codes supporting formality.
+/// The list is got with ``fetch_language_codes()``, so it is not downloaded again while cached.
+fn fetch_formality_languages(api_key: &String) -> Result<Vec<String>, RuntimeError> {
+    Ok(formality_language_codes(fetch_language_codes(api_key, LangType::Target)?))
+}
+/// Pick the language codes supporting formality.
+fn formality_language_codes(target_lang_codes: Vec<(LangCodeName, bool)>) -> Vec<String> {
+    target_lang_codes.into_iter().filter(|(_, formality)| *formality).map(|((code, _), _)| code).collect()
+}
+fn format_source_language_codes(source_lang_codes: &[LangCodeName]) -> String {
+    format_language_codes("Source language codes:", source_lang_codes, 3)
+}
+fn format_target_language_codes(target_lang_codes: &[(LangCodeName, bool)]) -> String {
+    // Mark languages supporting formality
+    let marked = target_lang_codes.iter().map(|((code, name), formality)| {
+        (code.clone(), if *formality { format!("{} *", name) } else { name.clone() })
+    }).collect::<Vec<LangCodeName>>();
+    let mut s = format_language_codes("Target languages:", &marked, 2);
+    s.push_str("(*: supports formality)\n");
+    s
+}
+fn format_all_language_codes(source_lang_codes: &[LangCodeName], target_lang_codes: &[(LangCodeName, bool)]) -> String {
+    format!("{}\n{}", format_source_language_codes(source_lang_codes), format_target_language_codes(target_lang_codes))
+}
+/// Format list of language codes in the specified number of columns.
+fn format_language_codes(title: &str, lang_codes: &[LangCodeName], columns: usize) -> String {
     let mut i = 0;
-    let (len, max_code_len, max_str_len) = get_langcodes_maxlen(&target_lang_codes);
+    let (len, max_code_len, max_str_len) = get_langcodes_maxlen(lang_codes);
 
-    println!("Target languages:");
-    for lang_code in target_lang_codes {
-        print!(" {lc:<cl$}: {ls:<sl$}", lc=lang_code.0.trim_matches('"'), ls=lang_code.1.trim_matches('"'), cl=max_code_len, sl=max_str_len);
+    let mut s = format!("{}\n", title);
+    for lang_code in lang_codes {
+        s.push_str(&format!(" {lc:<cl$}: {ls:<sl$}", lc=lang_code.0.trim_matches('"'), ls=lang_code.1.trim_matches('"'), cl=max_code_len, sl=max_str_len));
         i += 1;
-        if (i % 2) == 0 || i == len {
-            println!();
+        if (i % columns) == 0 || i == len {
+            s.push('\n');
         }
     }
-
-    Ok(())
+    s
 }
-fn get_langcodes_maxlen(lang_codes: &Vec<(String, String)>) -> (usize, usize, usize) {
+fn get_langcodes_maxlen(lang_codes: &[LangCodeName]) -> (usize, usize, usize) {
     let len = lang_codes.len();
-    let max_code_len = lang_codes.iter().map(|x| x.0.len()).max().unwrap();
-    let max_str_len = lang_codes.iter().map(|x| x.1.len()).max().unwrap();
+    let max_code_len = lang_codes.iter().map(|x| x.0.len()).max().unwrap_or(0);
+    let max_str_len = lang_codes.iter().map(|x| x.1.len()).max().unwrap_or(0);
     (len, max_code_len, max_str_len)
 }
 
 /// Get source text from the stdin.
-fn get_input(mode: &ExecutionMode, multilines: bool, rm_line_breaks: bool, text: &Option<String>) -> Option<Vec<String>> {
+/// Preprocessing of the input.
+/// In the plain mode, the input lines are sent as they are.
+fn preprocessing(plain: bool, line_breaks: LineBreaks, input_filter: InputFilter) -> (LineBreaks, InputFilter) {
+    if plain {
+        (LineBreaks::Keep, InputFilter::default())
+    } else {
+        (line_breaks, input_filter)
+    }
+}
+
+/// Apply the clean-up options to the input lines.
+fn filter_lines(lines: Vec<String>, filter: InputFilter) -> Vec<String> {
+    lines.into_iter()
+        .map(|line| if filter.trim { line.trim().to_string() } else { line })
+        .filter(|line| !filter.collapse_blank_lines || !line.trim().is_empty())
+        .collect()
+}
+
+fn get_input(mode: &ExecutionMode, multilines: bool, rm_line_breaks: bool, filter: InputFilter, text: &Option<String>) -> Option<Vec<String>> {
     let stdin = stdin();
     let mut stdout = stdout();
 
     match mode {
         ExecutionMode::TranslateInteractive => {
-            print!("> ");
+            interrupt::reset();
+            print!("{}", style::prompt("> "));
             stdout.flush().unwrap();
 
             let mut input_vec = Vec::<String>::new();
             let mut input = String::new();
             while stdin.read_line(&mut input).unwrap() > 0 {
+                // Ctrl-C: discard the lines entered so far (the prompt is shown again by the handler)
+                if interrupt::take_cancelled() {
+                    input_vec.clear();
+                    input.clear();
+                    continue;
+                }
                 if input.trim_end() == "quit" {
                     input_vec.push(input);
                     break;
@@ -271,17 +753,22 @@ fn get_input(mode: &ExecutionMode, multilines: bool, rm_line_breaks: bool, text:
                 // If not in multiline mode, accepts input containing line feeds with [\\ + newline].
                 else {
                     if input.ends_with("\n") && !input.ends_with("\\\r\n") && !input.ends_with("\\\n") {
-                        input_vec.push(input.trim_end().to_string());
+                        input_vec.push(parse::normalize_line_endings(input.trim_end()));
                         break;
                     }
                 }
 
-                input_vec.push(input.trim_end().to_string());
+                input_vec.push(parse::normalize_line_endings(input.trim_end()));
                 input.clear();
 
                 print!("..");
                 stdout.flush().unwrap();
             }
+            let input_vec = if matches!(input_vec.last(), Some(line) if line.trim_end() == "quit") {
+                input_vec
+            } else {
+                filter_lines(input_vec, filter)
+            };
             if rm_line_breaks {
                 let input_vec = vec![input_vec.join(" ")];
                 Some(input_vec)
@@ -292,129 +779,667 @@ fn get_input(mode: &ExecutionMode, multilines: bool, rm_line_breaks: bool, text:
         ExecutionMode::TranslateNormal => {
             match text {
                 Some(text) => {
+                    // Split strings containing newline codes.
+                    let lines = filter_lines(parse::normalize_line_endings(text).lines().map(|x| x.to_string()).collect(), filter);
                     if rm_line_breaks {
                         // Remove line breaks
-                        let text = text.lines().collect::<Vec<&str>>().join(" ");
-                        Some(vec![text])
+                        Some(vec![lines.join(" ")])
                     } else {
-                        // Split strings containing newline codes.
-                        let lines = text.lines();
-                        Some(lines.map(|x| x.to_string()).collect())
+                        Some(lines)
                     }
                 },
                 None => None
             }
         }
+        ExecutionMode::TranslateStream => {
+            read_stream_line(&mut stdin.lock()).map(|line| filter_lines(vec![line], filter))
+        }
         _ => {
             panic!("Invalid mode.");
         }
     }
 }
 
-/// Dialogue and Translation.
-/// Repeat input if in interactive mode
-/// In normal mode, it will be finished once
-fn process(api_key: &String, mode: ExecutionMode, source_lang: Option<String>, target_lang: String, 
-            multilines: bool, rm_line_breaks: bool, text: Option<String>, mut ofile: Option<std::fs::File>) -> Result<(), RuntimeError> {
-    // Translation
-    // loop if in interactive mode; exit once in normal mode
+/// Translate the text files in input_dir recursively and write them into output_dir with the same structure.
+/// Binary (non-UTF-8) files and the files not matching the glob pattern are skipped.
+/// Blank lines are kept as they are without being translated.
+/// translate is called once per file with the non-blank lines (not called for the blank files).
+/// Returns the number of the translated files.
+fn translate_directory(input_dir: &std::path::Path, output_dir: &std::path::Path, glob: Option<&str>,
+                        mut translate: impl FnMut(Vec<String>) -> Result<Vec<String>, RuntimeError>) -> Result<usize, RuntimeError> {
+    let pattern = match glob {
+        Some(glob) => Some(glob::Pattern::new(glob).map_err(|e| RuntimeError::StdIoError(format!("Invalid glob pattern: {}", e)))?),
+        None => None,
+    };
+    let mut count = 0;
+    for entry in walkdir::WalkDir::new(input_dir).sort_by_file_name() {
+        let entry = entry.map_err(|e| RuntimeError::FileIoError(e.to_string()))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative_path = entry.path().strip_prefix(input_dir).map_err(|e| RuntimeError::FileIoError(e.to_string()))?;
+        if matches!(&pattern, Some(pattern) if !pattern.matches_path(relative_path)) {
+            continue;
+        }
+        let bytes = std::fs::read(entry.path()).map_err(|e| RuntimeError::FileIoError(e.to_string()))?;
+        let text = match String::from_utf8(bytes) {
+            Ok(text) if !text.contains('\0') => text,
+            _ => {
+                log::debug!("skipped binary file: {}", relative_path.display());
+                continue;
+            }
+        };
 
-    // If it is interactive mode, it shows how to exit.
-    if mode == ExecutionMode::TranslateInteractive {
-        if source_lang.is_none() {
-            println!("Now translating from detected language to {}.", target_lang);
-        } else {
-            println!("Now translating from {} to {}.", source_lang.as_ref().unwrap(), target_lang);
+        // Translate the non-blank lines only
+        let lines = parse::normalize_line_endings(&text).lines().map(|line| line.to_string()).collect::<Vec<String>>();
+        let texts = lines.iter().filter(|line| !line.trim().is_empty()).cloned().collect::<Vec<String>>();
+        let mut translated = if texts.is_empty() { Vec::new() } else { translate(texts)? }.into_iter();
+        let output = lines.into_iter()
+            .map(|line| if line.trim().is_empty() { line } else { translated.next().unwrap_or_default() })
+            .collect::<Vec<String>>();
+
+        let output_path = output_dir.join(relative_path);
+        if let Some(parent) = output_path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| RuntimeError::FileIoError(e.to_string()))?;
         }
-        if multilines {
-            println!("Multiline mode: Enter a blank line to send the input.");
+        std::fs::write(&output_path, output.join("\n") + "\n").map_err(|e| RuntimeError::FileIoError(e.to_string()))?;
+        log::debug!("translated: {}", relative_path.display());
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Translate the phrases not in the cache yet and store them in the cache.
+/// Blank lines are ignored.
+/// Returns the numbers of the newly added phrases and the already cached phrases.
+fn warm_cache(phrases: &[String], source_lang: &Option<String>, target_lang: &str, scope: &str,
+                translate: impl Fn(Vec<String>) -> Result<Vec<String>, RuntimeError>) -> Result<(usize, usize), RuntimeError> {
+    let normalize = configure::get_cache_normalize_whitespace()?;
+    let mut cached = 0;
+    let mut misses = Vec::new();
+    for phrase in phrases.iter().filter(|phrase| !phrase.trim().is_empty()) {
+        let cache_str = if normalize { cache::normalize_whitespace(phrase) } else { phrase.trim().to_string() };
+        if cache::search_cache(&cache_str, source_lang, target_lang, scope)?.is_some() {
+            cached += 1;
+        } else if !misses.contains(&cache_str) {
+            misses.push(cache_str);
         }
-        println!("Type \"quit\" to exit dptran.");
+    }
+    if misses.is_empty() {
+        return Ok((0, cached));
     }
 
-    loop {
-        // If in interactive mode, get from standard input
-        // In normal mode, get from argument
-        let input = get_input(&mode, multilines, rm_line_breaks, &text);
-        if input.is_none() {
-            return Err(RuntimeError::DeeplApiError(DpTranError::CouldNotGetInputText));
+    let translated = translate(misses.clone())?;
+    let max_entries = get_cache_max_entries()?;
+    for (cache_str, translated) in misses.iter().zip(&translated) {
+        cache::into_cache_element(cache_str, translated, source_lang, target_lang, scope, max_entries)?;
+    }
+    Ok((translated.len().min(misses.len()), cached))
+}
+
+/// Search the cache and log whether it was a hit or a miss.
+fn search_cache(cache_str: &str, source_lang: &Option<String>, target_lang: &String, scope: &str) -> Result<Option<String>, RuntimeError> {
+    let cache_result = cache::search_cache(cache_str, source_lang, target_lang, scope)?;
+    let source_lang_str = source_lang.as_deref().unwrap_or("auto");
+    if cache_result.is_some() {
+        // The key helps to find the entry in the cache file
+        log::debug!("cache hit: {} -> {} (key: {})", source_lang_str, target_lang, cache::cache_hash(cache_str, source_lang, target_lang, scope));
+    } else {
+        log::debug!("cache miss: {} -> {}", source_lang_str, target_lang);
+    }
+    Ok(cache_result)
+}
+
+/// Create or open the output file.
+/// In append mode, the translations are appended to the existing file.
+/// Otherwise, if the file exists, ask whether to overwrite it and return None if not.
+fn create_or_open_file(output_file: &str, append: bool) -> Result<Option<std::fs::File>, RuntimeError> {
+    if append {
+        let ofile = OpenOptions::new().create(true).append(true).open(output_file)
+            .map_err(|e| RuntimeError::FileIoError(e.to_string()))?;
+        return Ok(Some(ofile));
+    }
+    // is the file exists?
+    if std::path::Path::new(output_file).exists() {
+        print!("The file {} already exists. Overwrite? (y/N) ", output_file);
+        std::io::stdout().flush().unwrap();
+        let mut input = String::new();
+        io::stdin().read_line(&mut input).unwrap();
+        if !input.trim().eq_ignore_ascii_case("y") {
+            return Ok(None);
         }
+    }
+    let ofile = OpenOptions::new().create(true).write(true).truncate(true).open(output_file)
+        .map_err(|e| RuntimeError::FileIoError(e.to_string()))?;
+    Ok(Some(ofile))
+}
 
-        // Interactive mode: "quit" to exit
-        if mode == ExecutionMode::TranslateInteractive {
-            if let Some(input) = &input {
-                if input.len() == 0 {
-                    continue;
-                }
-                if input[0].trim_end() == "quit" {
-                    break;
-                }
-                if input[0].clone().trim_end().is_empty() {
-                    continue;
-                }
+/// Output the translated texts to the stdout or the output file.
+fn output_translated_texts(mode: &ExecutionMode, translated_texts: Vec<String>, ofile: &mut Option<std::fs::File>) -> Result<(), RuntimeError> {
+    let _guard = interrupt::write_guard();
+    for translated_text in translated_texts {
+        if let Some(ofile) = ofile {
+            // append to the file
+            let mut buf_writer = BufWriter::new(ofile);
+            writeln!(buf_writer, "{}", translated_text).map_err(|e| RuntimeError::FileIoError(e.to_string()))?;
+            if *mode == ExecutionMode::TranslateInteractive {
+                println!("{}", style::translated(&translated_text));
             }
+        } else {
+            println!("{}", style::translated(&translated_text));
         }
-        // Normal mode: Exit if empty string
-        if mode == ExecutionMode::TranslateNormal && input.is_none() {
-            break;
-        }
+    }
+    Ok(())
+}
 
-        // Check the cache
-        let cache_enabled = configure::get_cache_enabled().map_err(|e| RuntimeError::ConfigError(e))?;
-        let cache_str = input.clone().unwrap().join("\n").trim().to_string();
-        let cache_result = if cache_enabled {
-            cache::search_cache(&cache_str, &source_lang, &target_lang).map_err(|e| RuntimeError::CacheError(e))?
-        } else {
-            None
-        };
-        let translated_texts = if let Some(cached_text) = cache_result {
-            vec![cached_text]
-        // If not in cache, translate and store in cache
-        } else {
-            // translate
-            let result = dptran::translate(&api_key, input.clone().unwrap(), &target_lang, &source_lang)
-                .map_err(|e| RuntimeError::DeeplApiError(e))?;
-            // replace \" with "
-            let result = result.iter().map(|x| x.replace(r#"\""#, "\"")).collect::<Vec<String>>();
-            // store in cache
-            let max_entries = get_cache_max_entries()?;
-            if cache_enabled {
-                cache::into_cache_element(&cache_str, &result.clone().join("\n"), &source_lang, &target_lang, max_entries)
-                    .map_err(|e| RuntimeError::FileIoError(e.to_string()))?;
-            }
-            result
-        };
-        for translated_text in translated_texts {
-            if let Some(ofile) = &mut ofile {
-                // append to the file
-                let mut buf_writer = BufWriter::new(ofile);
-                writeln!(buf_writer, "{}", translated_text).map_err(|e| RuntimeError::FileIoError(e.to_string()))?;
-                if mode == ExecutionMode::TranslateInteractive {
-                    println!("{}", translated_text);
-                }
-            } else {
-                println!("{}", translated_text);
+/// Check that the target language supports the formality before sending.
+/// ``more`` / ``less`` on an unsupported language is an error,
+///   or is substituted with ``prefer_more`` / ``prefer_less`` if fallback is true.
+/// Returns the formality to send.
+fn check_formality(formality: Formality, target_lang: &str, supported_langs: &[String], fallback: bool) -> Result<Formality, RuntimeError> {
+    let strict = formality == Formality::More || formality == Formality::Less;
+    if !strict || supported_langs.iter().any(|lang| lang.eq_ignore_ascii_case(target_lang)) {
+        Ok(formality)
+    } else if fallback {
+        Ok(formality.to_prefer())
+    } else {
+        Err(RuntimeError::FormalityNotSupported(target_lang.to_string(), supported_langs.to_vec()))
+    }
+}
+
+/// Default formality in the settings to use for the target language.
+/// It is skipped silently if the target language does not support formality.
+fn apply_default_formality(default_formality: Formality, target_lang: &str, supported_langs: &[String]) -> Option<Formality> {
+    if supported_langs.iter().any(|lang| lang.eq_ignore_ascii_case(target_lang)) {
+        Some(default_formality)
+    } else {
+        None
+    }
+}
+
+/// Parse the language pair "SOURCE:TARGET" of `dptran set --glossary-for`.
+fn parse_language_pair(pair: &str) -> Result<(String, String), RuntimeError> {
+    match pair.split_once(':') {
+        Some((source_lang, target_lang)) if !source_lang.is_empty() && !target_lang.is_empty() => Ok((source_lang.to_string(), target_lang.to_string())),
+        _ => Err(RuntimeError::StdIoError(format!("Invalid language pair '{}'. Specify it as SOURCE:TARGET (e.g. EN:JA).", pair))),
+    }
+}
+
+/// Select the glossary to apply.
+/// `--glossary-id` wins over the glossary configured for the language pair.
+/// The configured glossary is skipped if the source language is detected automatically, since DeepL API requires the source language for glossaries.
+fn select_glossary_id(arg_glossary_id: Option<String>, source_lang: &Option<String>, target_lang: &str) -> Result<Option<String>, RuntimeError> {
+    if arg_glossary_id.is_some() {
+        return Ok(arg_glossary_id);
+    }
+    match source_lang {
+        Some(source_lang) => Ok(configure::get_glossary_for(source_lang, target_lang)?),
+        None => Ok(None),
+    }
+}
+
+/// Report of the glossary applied to the translation.
+fn glossary_report(translate_options: &TranslateOptions) -> String {
+    match &translate_options.glossary_id {
+        Some(glossary_id) => format!("Glossary applied: {}", glossary_id),
+        None => "No glossary applied".to_string(),
+    }
+}
+
+/// Join the lines in each paragraph with spaces.
+/// Paragraphs are separated by blank lines.
+fn reflow_paragraphs(lines: &[String]) -> Vec<String> {
+    let mut paragraphs = Vec::new();
+    let mut paragraph = Vec::new();
+    for line in lines {
+        if line.trim().is_empty() {
+            if !paragraph.is_empty() {
+                paragraphs.push(paragraph.join(" "));
+                paragraph.clear();
             }
+        } else {
+            paragraph.push(line.trim());
         }
-        // In normal mode, exit the loop once.
-        if mode == ExecutionMode::TranslateNormal {
-            break;
+    }
+    if !paragraph.is_empty() {
+        paragraphs.push(paragraph.join(" "));
+    }
+    paragraphs
+}
+
+/// Put a blank line between the paragraphs.
+fn separate_paragraphs(paragraphs: Vec<String>) -> Vec<String> {
+    let mut lines = Vec::new();
+    for (i, paragraph) in paragraphs.into_iter().enumerate() {
+        if i > 0 {
+            lines.push(String::new());
         }
+        lines.push(paragraph);
     }
+    lines
+}
 
-    Ok(())
+/// split_sentences parameter of the translation.
+/// In the reflow mode, `nonewlines` is used unless specified.
+fn select_split_sentences(split_sentences: Option<SplitSentences>, line_breaks: LineBreaks) -> Option<SplitSentences> {
+    match split_sentences {
+        Some(split_sentences) => Some(split_sentences),
+        None if line_breaks == LineBreaks::Reflow => Some(SplitSentences::NoNewlines),
+        None => None,
+    }
 }
 
-/// Obtaining arguments and calling the translation process
-fn main() -> Result<(), RuntimeError> {
+/// Record the translation request in the audit log if it is enabled.
+/// Only the metadata is recorded, not the texts.
+fn audit_translation(input: &[String], source_lang: &Option<String>, target_lang: &str, success: bool) -> Result<(), RuntimeError> {
+    if let Some(path) = configure::get_audit_log_path()? {
+        let chars = input.iter().map(|line| line.chars().count()).sum();
+        let record = audit::AuditRecord::new(source_lang, target_lang, chars, &dptran::translate_endpoint(), success);
+        audit::append(std::path::Path::new(&path), &record).map_err(|e| RuntimeError::FileIoError(e.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Age in seconds of `cache --prune --older-than <days>`.
+/// Saturated for very large numbers of days instead of overflowing.
+fn older_than_secs(days: u64) -> u64 {
+    days.saturating_mul(24 * 60 * 60)
+}
+
+/// Whether the cache is used in this run.
+/// ``--cache`` / ``--no-cache`` (cache_override) take precedence over the setting.
+fn use_cache(cache_enabled: bool, cache_override: Option<bool>) -> bool {
+    cache_override.unwrap_or(cache_enabled)
+}
+
+/// Scope of the cache entries for the translation parameters affecting the result.
+/// The translations with a glossary, formality or other parameters are cached separately from the plain ones.
+/// So are the results of `--skip-same` / `--skip-if-detected`, which contain untranslated lines.
+fn cache_scope(translate_options: &TranslateOptions, skip_detected: Option<&str>) -> String {
+    let mut scope = Vec::new();
+    if let Some(glossary_id) = &translate_options.glossary_id {
+        scope.push(format!("glossary={}", glossary_id));
+    }
+    if let Some(formality) = &translate_options.formality {
+        scope.push(format!("formality={}", formality));
+    }
+    if let Some(split_sentences) = &translate_options.split_sentences {
+        scope.push(format!("split_sentences={}", split_sentences));
+    }
+    for (key, value) in &translate_options.extra_params {
+        scope.push(format!("{}={}", key, value));
+    }
+    if let Some(skip_lang) = skip_detected {
+        scope.push(format!("skip_detected={}", skip_lang.to_uppercase()));
+    }
+    scope.join("&")
+}
+
+/// Put each source segment immediately above its translation.
+/// If the segments do not correspond one-to-one (e.g. the translation is from the cache),
+/// the whole source is put above the whole translation.
+fn interleave_source(source: &[String], translated_texts: Vec<String>) -> Vec<String> {
+    if source.len() != translated_texts.len() {
+        return vec![source.join("\n"), translated_texts.join("\n")];
+    }
+    source.iter().zip(translated_texts).flat_map(|(s, t)| [s.clone(), t]).collect()
+}
+
+/// Warning for a short input whose source language is detected automatically.
+/// threshold: 0 disables the warning.
+fn short_input_warning(input: &[String], source_lang: &Option<String>, threshold: usize) -> Option<String> {
+    let chars = input.iter().map(|x| x.trim().chars().count()).sum::<usize>();
+    if source_lang.is_none() && chars < threshold {
+        Some("Warning: The input is too short to detect the source language reliably. Consider specifying it with --from.".to_string())
+    } else {
+        None
+    }
+}
+
+/// Count the characters (Unicode scalar values) of the input.
+fn count_chars(input: &[String]) -> usize {
+    input.iter().map(|x| x.chars().count()).sum()
+}
+
+/// Check that the input does not exceed the maximum characters per run.
+/// Returns the number of characters sent in this run including the input.
+/// max_chars: 0 means unlimited.
+fn check_max_chars(input: &[String], sent_chars: usize, max_chars: usize) -> Result<usize, RuntimeError> {
+    let requested = sent_chars + count_chars(input);
+    if max_chars > 0 && requested > max_chars {
+        return Err(RuntimeError::MaxCharsExceeded(requested, max_chars));
+    }
+    Ok(requested)
+}
+
+/// Read a line from the stream without the line break.
+/// Returns None at the end of the stream.
+fn read_stream_line(reader: &mut impl BufRead) -> Option<String> {
+    let mut line = String::new();
+    match reader.read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim_end_matches(['\r', '\n']).to_string()),
+    }
+}
+
+/// Counters of a translation run, printed by `--summary`.
+#[derive(Debug, Default, PartialEq)]
+struct RunSummary {
+    /// Number of the translated lines (segments)
+    segments: usize,
+    cache_hits: usize,
+    api_calls: usize,
+    /// Number of characters billed, as returned by DeepL API (`billed_characters`)
+    chars_billed: u64,
+}
+
+impl RunSummary {
+    /// Count a translation found in the cache.
+    fn add_cache_hit(&mut self, segments: usize) {
+        self.segments += segments;
+        self.cache_hits += 1;
+    }
+
+    /// Count a translation sent to DeepL API.
+    fn add_api_call(&mut self, input: &[String]) {
+        self.segments += input.len();
+        self.api_calls += 1;
+    }
+
+    /// Count the characters billed for the translations.
+    fn add_billed(&mut self, translations: &[DpTranTranslation]) {
+        self.chars_billed += translations.iter().filter_map(|translation| translation.billed_characters).sum::<u64>();
+    }
+
+    /// Summary line of the run.
+    fn report(&self, elapsed: std::time::Duration) -> String {
+        format!("Summary: {} segments, {} cache hits, {} API calls, {} characters billed, {:.2}s elapsed",
+            self.segments, self.cache_hits, self.api_calls, self.chars_billed, elapsed.as_secs_f64())
+    }
+}
+
+/// Whether the detected language is the language (ignoring the variants, e.g. EN-US is EN).
+fn is_same_language(detected_lang: &str, lang: &str) -> bool {
+    let base = |lang: &str| lang.split('-').next().unwrap_or("").to_ascii_uppercase();
+    base(detected_lang) == base(lang)
+}
+
+/// Replace the translations of the lines detected as skip_lang with the lines themselves.
+fn keep_detected_lines(input: &[String], translations: Vec<DpTranTranslation>, skip_lang: &str) -> Vec<String> {
+    input.iter().zip(translations).map(|(line, translation)| {
+        match &translation.detected_source_language {
+            Some(detected_lang) if is_same_language(detected_lang, skip_lang) => line.clone(),
+            _ => translation.text,
+        }
+    }).collect()
+}
+
+/// Whether to exit after a translation.
+/// Normal mode and `--interactive-once` translate only once.
+fn exits_after_translation(mode: &ExecutionMode, once: bool) -> bool {
+    *mode == ExecutionMode::TranslateNormal || (*mode == ExecutionMode::TranslateInteractive && once)
+}
+
+/// Dialogue and Translation.
+/// Repeat input if in interactive mode
+/// In normal mode, it will be finished once
+/// If once is true, interactive mode reads and translates only one input.
+/// Returns the counters of the run.
+fn process(api_key: &String, mode: ExecutionMode, source_lang: Option<String>, target_lang: String, 
+            run_options: RunOptions, translate_options: TranslateOptions, text: Option<String>, mut ofile: Option<std::fs::File>) -> Result<RunSummary, RuntimeError> {
+    let RunOptions { multilines, once, line_breaks, input_filter, keep_going, skip_detected, show_source, cache_override, max_chars, detect_threshold } = run_options;
+
+    // Translation
+    // loop if in interactive mode; exit once in normal mode
+
+    // If it is interactive mode, it shows how to exit.
+    if mode == ExecutionMode::TranslateInteractive && !once {
+        if source_lang.is_none() {
+            println!("Now translating from detected language to {}.", target_lang);
+        } else {
+            println!("Now translating from {} to {}.", source_lang.as_ref().unwrap(), target_lang);
+        }
+        if multilines {
+            println!("Multiline mode: Enter a blank line to send the input.");
+        }
+        println!("Type \"quit\" to exit dptran.");
+    }
+    // Interactive mode: Ctrl-C cancels the input instead of killing dptran in the middle of a write
+    if mode == ExecutionMode::TranslateInteractive {
+        if let Err(e) = interrupt::install() {
+            log::warn!("failed to install the Ctrl-C handler: {}", e);
+        }
+    }
+
+    // Number of characters sent to DeepL API in this run
+    let mut sent_chars = 0;
+    let mut summary = RunSummary::default();
+
+    loop {
+        // If in interactive mode, get from standard input
+        // In normal mode, get from argument
+        let input = get_input(&mode, multilines, line_breaks == LineBreaks::Remove, input_filter, &text);
+        let input = if line_breaks == LineBreaks::Reflow {
+            input.map(|lines| reflow_paragraphs(&lines))
+        } else {
+            input
+        };
+        // Stream mode: Exit at the end of the stream
+        if mode == ExecutionMode::TranslateStream && input.is_none() {
+            break;
+        }
+        if input.is_none() {
+            return Err(RuntimeError::DeeplApiError(DpTranError::CouldNotGetInputText));
+        }
+        // Stream mode: Skip the removed blank lines and keep the others as they are
+        if mode == ExecutionMode::TranslateStream && input.as_ref().unwrap().is_empty() {
+            continue;
+        }
+        if mode == ExecutionMode::TranslateStream && input.as_ref().unwrap()[0].trim().is_empty() {
+            output_translated_texts(&mode, vec![String::new()], &mut ofile)?;
+            continue;
+        }
+
+        // Interactive mode: "quit" to exit
+        if mode == ExecutionMode::TranslateInteractive {
+            if let Some(input) = &input {
+                if !input.is_empty() && input[0].trim_end() == "quit" {
+                    break;
+                }
+                // Nothing to translate (interactive-once mode exits)
+                if input.is_empty() || input[0].trim_end().is_empty() {
+                    if once {
+                        break;
+                    }
+                    continue;
+                }
+            }
+        }
+        // Normal mode: Exit if empty string
+        if mode == ExecutionMode::TranslateNormal && input.is_none() {
+            break;
+        }
+        // Normal mode: Nothing to translate if the input is empty or only whitespace
+        if mode == ExecutionMode::TranslateNormal && input.as_ref().unwrap().iter().all(|line| line.trim().is_empty()) {
+            log::debug!("input is empty: nothing to translate");
+            break;
+        }
+
+        // Check the cache
+        let cache_enabled = use_cache(configure::get_cache_enabled()?, cache_override);
+        let scope = cache_scope(&translate_options, skip_detected.as_deref());
+        let cache_str = if line_breaks == LineBreaks::Reflow {
+            separate_paragraphs(input.clone().unwrap())
+        } else {
+            input.clone().unwrap()
+        }.join("\n").trim().to_string();
+        // Ignore trivial whitespace differences (applied to both lookup and store)
+        let cache_str = if configure::get_cache_normalize_whitespace()? {
+            cache::normalize_whitespace(&cache_str)
+        } else {
+            cache_str
+        };
+        let cache_result = if cache_enabled {
+            search_cache(&cache_str, &source_lang, &target_lang, &scope)?
+        } else {
+            None
+        };
+        let translated_texts = if let Some(cached_text) = cache_result {
+            summary.add_cache_hit(input.as_ref().unwrap().len());
+            vec![cached_text]
+        // If not in cache, translate and store in cache
+        } else {
+            // Abort before sending if the input exceeds the limit
+            sent_chars = check_max_chars(input.as_ref().unwrap(), sent_chars, max_chars)?;
+            // Auto-detection is unreliable on very short inputs
+            if let Some(warning) = short_input_warning(input.as_ref().unwrap(), &source_lang, detect_threshold) {
+                eprintln!("{}", warning);
+            }
+            // translate
+            summary.add_api_call(input.as_ref().unwrap());
+            let translations = if keep_going {
+                let result = dptran::translate_partial_detailed(api_key, input.clone().unwrap(), &target_lang, &source_lang, &translate_options);
+                audit_translation(input.as_ref().unwrap(), &source_lang, &target_lang, result.is_ok())?;
+                match result {
+                    Ok(result) => result,
+                    Err(e) => {
+                        // output the translations completed before the failure
+                        output_translated_texts(&mode, e.translated, &mut ofile)?;
+                        eprintln!("Lines {}-{} were not translated.", e.failed_range.start + 1, e.failed_range.end);
+                        return Err(RuntimeError::DeeplApiError(e.error));
+                    }
+                }
+            } else {
+                let result = dptran::translate_detailed(api_key, input.clone().unwrap(), &target_lang, &source_lang, &translate_options);
+                audit_translation(input.as_ref().unwrap(), &source_lang, &target_lang, result.is_ok())?;
+                result?
+            };
+            summary.add_billed(&translations);
+            let result = if let Some(skip_lang) = &skip_detected {
+                keep_detected_lines(input.as_ref().unwrap(), translations, skip_lang)
+            } else {
+                translations.into_iter().map(|translation| translation.text).collect()
+            };
+            // Reflow: separate the paragraphs with blank lines
+            let result = if line_breaks == LineBreaks::Reflow {
+                separate_paragraphs(result)
+            } else {
+                result
+            };
+            // store in cache
+            let max_entries = get_cache_max_entries()?;
+            if cache_enabled {
+                let _guard = interrupt::write_guard();
+                cache::into_cache_element(&cache_str, &result.clone().join("\n"), &source_lang, &target_lang, &scope, max_entries)
+                    .map_err(|e| RuntimeError::FileIoError(e.to_string()))?;
+            }
+            result
+        };
+        let translated_texts = if show_source {
+            interleave_source(input.as_ref().unwrap(), translated_texts)
+        } else {
+            translated_texts
+        };
+        output_translated_texts(&mode, translated_texts, &mut ofile)?;
+        // In normal mode, exit the loop once.
+        if exits_after_translation(&mode, once) {
+            break;
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Set the output code page of the console to UTF-8 (65001).
+/// Otherwise, CJK characters and emoji are garbled in cmd.exe with the legacy code page (e.g. 932).
+#[cfg(target_os = "windows")]
+fn set_console_output_utf8() {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn SetConsoleOutputCP(code_page: u32) -> i32;
+    }
+    // Failure (e.g. no console attached) is ignored
+    unsafe {
+        SetConsoleOutputCP(65001);
+    }
+}
+
+/// Run dptran and exit with the exit code corresponding to the error.
+/// See `RuntimeError::exit_code()` for the exit codes.
+/// On Windows, the console output code page is switched to UTF-8 before anything is printed.
+fn main() {
+    #[cfg(target_os = "windows")]
+    set_console_output_utf8();
+
+    if let Err(e) = run() {
+        eprintln!("{}", style::error(&error_message(&e)));
+        std::process::exit(e.exit_code());
+    }
+}
+
+/// Message printed when dptran exits with the error.
+fn error_message(e: &RuntimeError) -> String {
+    if let RuntimeError::DeeplApiError(DpTranError::DeeplApiError(dptran::DeeplAPIError::LimitError)) = e {
+        // Show the current usage to make it clear why the translation failed
+        limit_error_message(&get_usage().ok())
+    } else {
+        format!("Error: {}", e.to_string())
+    }
+}
+
+/// Message for the translation limit error.
+fn limit_error_message(usage: &Option<DpTranUsage>) -> String {
+    let mut message = "Error: The translation limit of your account has been reached.".to_string();
+    if let Some(usage) = usage {
+        if !usage.unlimited {
+            message.push_str(&format!("\nusage: {}", usage));
+        }
+    }
+    message.push_str("\nConsider upgrading your subscription: https://www.deepl.com/pro-api");
+    message
+}
+
+/// Obtaining arguments and calling the translation process
+fn run() -> Result<(), RuntimeError> {
     // Parsing arguments.
     let arg_struct = parse::parser()?;
+    logger::init(arg_struct.verbose);
+    style::init(arg_struct.color);
+    dptran::set_connect_timeout(arg_struct.connect_timeout.map(std::time::Duration::from_secs));
+    dptran::set_request_timeout(arg_struct.request_timeout.map(std::time::Duration::from_secs));
+    dptran::set_retry_policy(arg_struct.max_retries, std::time::Duration::from_millis(arg_struct.retry_delay));
     let mode = arg_struct.execution_mode;
     match mode {
         ExecutionMode::PrintUsage => {
             show_usage()?;
             return Ok(());
         }
+        ExecutionMode::PrintBuildInfo => {
+            print!("{}", build_info());
+            return Ok(());
+        }
+        ExecutionMode::Doctor => {
+            doctor()?;
+            return Ok(());
+        }
+        ExecutionMode::Ping => {
+            ping()?;
+            return Ok(());
+        }
+        ExecutionMode::Info => {
+            info(arg_struct.json)?;
+            return Ok(());
+        }
+        ExecutionMode::GenerateCompletions => {
+            if let Some(shell) = arg_struct.completion_shell {
+                parse::generate_completions(shell, &mut stdout());
+            }
+            return Ok(());
+        }
+        ExecutionMode::GenerateManPage => {
+            parse::generate_man_page(&mut stdout()).map_err(|e| RuntimeError::StdIoError(e.to_string()))?;
+            return Ok(());
+        }
         ExecutionMode::SetApiKey => {
             if let Some(s) = arg_struct.api_key {
                 set_api_key(s)?;
@@ -431,16 +1456,47 @@ fn main() -> Result<(), RuntimeError> {
                 return Err(RuntimeError::DeeplApiError(DpTranError::NoTargetLanguageSpecified));
             }
         }
+        ExecutionMode::ResetDefaultTargetLang => {
+            reset_default_target_language()?;
+            return Ok(());
+        }
         ExecutionMode::SetCacheMaxEntries => {
             if let Some(s) = arg_struct.cache_max_entries {
-                configure::set_cache_max_entries(s).map_err(|e| RuntimeError::ConfigError(e))?;
+                configure::set_cache_max_entries(s)?;
                 return Ok(());
             } else {
                 return Err(RuntimeError::StdIoError("Cache max entries is not specified.".to_string()));
             }
         }
         ExecutionMode::ClearCache => {
-            cache::clear_cache().map_err(|e| RuntimeError::CacheError(e))?;
+            cache::clear_cache()?;
+            return Ok(());
+        }
+        ExecutionMode::ExportCache => {
+            let path = arg_struct.cache_file.unwrap_or_default();
+            let count = cache::export_cache(std::path::Path::new(&path))?;
+            println!("{} cache entries exported to {}.", count, path);
+            return Ok(());
+        }
+        ExecutionMode::ImportCache => {
+            let path = arg_struct.cache_file.unwrap_or_default();
+            let count = cache::import_cache(std::path::Path::new(&path), get_cache_max_entries()?)?;
+            println!("{} cache entries imported from {}.", count, path);
+            return Ok(());
+        }
+        ExecutionMode::ListCache => {
+            for (hash, source_lang, target_lang, value) in cache::get_cache_entries()? {
+                println!("{}  {} -> {}  {}", hash, source_lang.unwrap_or("auto".to_string()), target_lang, value.replace('\n', "\\n"));
+            }
+            return Ok(());
+        }
+        ExecutionMode::PruneCache => {
+            if arg_struct.translate_to.is_none() && arg_struct.prune_older_than.is_none() {
+                return Err(RuntimeError::StdIoError("Neither target language nor age is specified (e.g. `dptran cache --prune --to JA` or `dptran cache --prune --older-than 30`).".to_string()));
+            }
+            let older_than = arg_struct.prune_older_than.map(older_than_secs);
+            let count = cache::prune_cache(&arg_struct.translate_from, &arg_struct.translate_to, older_than)?;
+            println!("{} cache entries removed.", count);
             return Ok(());
         }
         ExecutionMode::SetEditor => {
@@ -451,16 +1507,85 @@ fn main() -> Result<(), RuntimeError> {
                 return Err(RuntimeError::StdIoError("Editor command is not specified.".to_string()));
             }
         }
+        ExecutionMode::SetLegacyLangExpansion => {
+            if let Some(b) = arg_struct.legacy_lang_expansion {
+                set_legacy_lang_expansion(b)?;
+                return Ok(());
+            } else {
+                return Err(RuntimeError::StdIoError("Legacy language code expansion is not specified.".to_string()));
+            }
+        }
+        ExecutionMode::SetMaxCharsPerRun => {
+            if let Some(n) = arg_struct.max_chars_per_run {
+                configure::set_max_chars_per_run(n)?;
+                return Ok(());
+            } else {
+                return Err(RuntimeError::StdIoError("Max characters per run is not specified.".to_string()));
+            }
+        }
+        ExecutionMode::SetDefaultFormality => {
+            if let Some(formality) = arg_struct.default_formality {
+                configure::set_default_formality(formality)?;
+                return Ok(());
+            } else {
+                return Err(RuntimeError::StdIoError("Formality is not specified.".to_string()));
+            }
+        }
+        ExecutionMode::SetCacheNormalizeWhitespace => {
+            if let Some(b) = arg_struct.cache_normalize_whitespace {
+                configure::set_cache_normalize_whitespace(b)?;
+                return Ok(());
+            } else {
+                return Err(RuntimeError::StdIoError("Cache whitespace normalization is not specified.".to_string()));
+            }
+        }
+        ExecutionMode::SetGlossaryFor => {
+            if let Some((pair, glossary_id)) = arg_struct.glossary_for {
+                let (source_lang, target_lang) = parse_language_pair(&pair)?;
+                configure::set_glossary_for(&source_lang, &target_lang, if glossary_id.is_empty() { None } else { Some(glossary_id) })?;
+                return Ok(());
+            } else {
+                return Err(RuntimeError::StdIoError("Language pair and glossary ID are not specified.".to_string()));
+            }
+        }
+        ExecutionMode::ExportSettings => {
+            let path = arg_struct.settings_file.unwrap_or_default();
+            configure::export_settings(std::path::Path::new(&path), arg_struct.include_api_key)?;
+            println!("Settings exported to {}.", path);
+            return Ok(());
+        }
+        ExecutionMode::ImportSettings => {
+            let path = arg_struct.settings_file.unwrap_or_default();
+            configure::import_settings(std::path::Path::new(&path))?;
+            println!("Settings imported from {}.", path);
+            return Ok(());
+        }
+        ExecutionMode::SetRememberLastLangs => {
+            if let Some(b) = arg_struct.remember_last_langs {
+                configure::set_remember_last_langs(b)?;
+                return Ok(());
+            } else {
+                return Err(RuntimeError::StdIoError("Remembering the last languages is not specified.".to_string()));
+            }
+        }
+        ExecutionMode::SetAuditLogPath => {
+            if let Some(path) = arg_struct.audit_log_path {
+                configure::set_audit_log_path(if path.is_empty() { None } else { Some(path) })?;
+                return Ok(());
+            } else {
+                return Err(RuntimeError::StdIoError("Audit log path is not specified.".to_string()));
+            }
+        }
         ExecutionMode::EnableCache => {
-            configure::set_cache_enabled(true).map_err(|e| RuntimeError::ConfigError(e))?;
+            configure::set_cache_enabled(true)?;
             return Ok(());
         }
         ExecutionMode::DisableCache => {
-            configure::set_cache_enabled(false).map_err(|e| RuntimeError::ConfigError(e))?;
+            configure::set_cache_enabled(false)?;
             return Ok(());
         }
         ExecutionMode::DisplaySettings => {
-            display_settings()?;
+            display_settings(arg_struct.json)?;
             return Ok(());
         }
         ExecutionMode::ClearSettings => {
@@ -475,16 +1600,40 @@ fn main() -> Result<(), RuntimeError> {
             show_target_language_codes()?;
             return Ok(());
         }
-        _ => {}     // ExecutionMode::TranslateNormal, ExecutionMode::TranslateInteractive, ExecutionMode::FileInput
+        ExecutionMode::ListAllLangs => {
+            show_all_language_codes()?;
+            return Ok(());
+        }
+        _ => {}     // ExecutionMode::TranslateNormal, ExecutionMode::TranslateInteractive, ExecutionMode::TranslateStream, ExecutionMode::TranslateDirectory, ExecutionMode::WarmCache
     };
 
-    let mut source_lang = arg_struct.translate_from;
-    let mut target_lang = arg_struct.translate_to;
+    // Handling of the line breaks and clean-up of the input (all disabled by --plain)
+    let line_breaks = if arg_struct.reflow {
+        LineBreaks::Reflow
+    } else if arg_struct.remove_line_breaks {
+        LineBreaks::Remove
+    } else {
+        LineBreaks::Keep
+    };
+    let input_filter = InputFilter {
+        trim: arg_struct.trim,
+        collapse_blank_lines: arg_struct.collapse_blank_lines,
+    };
+    let (line_breaks, input_filter) = preprocessing(arg_struct.plain, line_breaks, input_filter);
 
-    if target_lang.is_none() {
-        target_lang = Some(get_default_target_language_code()?);
+    // Only count the characters of the input (no API request)
+    if arg_struct.count_only {
+        if mode != ExecutionMode::TranslateNormal {
+            return Err(RuntimeError::StdIoError("No input to count.".to_string()));
+        }
+        let input = get_input(&mode, arg_struct.multilines, line_breaks == LineBreaks::Remove, input_filter, &arg_struct.source_text).unwrap_or_default();
+        println!("{}", count_chars(&input));
+        return Ok(());
     }
 
+    let mut source_lang = arg_struct.translate_from;
+    let mut target_lang = Some(select_target_language(arg_struct.translate_to, std::env::var(DEFAULT_TARGET_LANG_ENV).ok(), configure::get_fallback_target_language_code()?));
+
     // API Key confirmation
     let api_key = match get_api_key()? {
         Some(api_key) => api_key,
@@ -496,35 +1645,974 @@ fn main() -> Result<(), RuntimeError> {
 
     // Language code check and correction
     if let Some(sl) = source_lang {
-        source_lang = Some(dptran::correct_source_language_code(&api_key, &sl.to_string()).map_err(|e| RuntimeError::DeeplApiError(e))?);
+        source_lang = Some(correct_language_code(&api_key, &sl, LangType::Source)?);
     }
     if let Some(tl) = target_lang {
-        target_lang = Some(dptran::correct_target_language_code(&api_key, &tl.to_string()).map_err(|e| RuntimeError::DeeplApiError(e))?);
+        target_lang = Some(correct_language_code(&api_key, &tl, LangType::Target)?);
     }
 
     // Output filepath
-    // If output file is specified, it will be created or overwritten.
+    // If output file is specified, it will be created, overwritten or appended.
     let ofile = if let Some(output_file) = arg_struct.ofile_path {
-        // is the file exists?
-        if std::path::Path::new(&output_file).exists() {
-            print!("The file {} already exists. Overwrite? (y/N) ", output_file);
-            std::io::stdout().flush().unwrap();
-            let mut input = String::new();
-            io::stdin().read_line(&mut input).unwrap();
-            if input.trim().to_ascii_lowercase() != "y" {
-                return Ok(());  // Do not overwrite
-            }
+        match create_or_open_file(&output_file, arg_struct.append)? {
+            Some(ofile) => Some(ofile),
+            None => return Ok(()),  // Do not overwrite
         }
-        Some(OpenOptions::new().create(true).write(true).truncate(true).open(&output_file)
-            .map_err(|e| RuntimeError::FileIoError(e.to_string()))?)
     }
     else {
         None
     };
 
+    // Check if the target language supports formality
+    let formality = match arg_struct.formality {
+        Some(formality) if formality == Formality::More || formality == Formality::Less => {
+            let supported_langs = fetch_formality_languages(&api_key)?;
+            Some(check_formality(formality, target_lang.as_ref().unwrap(), &supported_langs, arg_struct.formality_fallback)?)
+        },
+        Some(formality) => Some(formality),
+        // Default formality in the settings
+        None => match configure::get_default_formality()? {
+            Some(default_formality) if default_formality != Formality::Default => {
+                let supported_langs = fetch_formality_languages(&api_key)?;
+                apply_default_formality(default_formality, target_lang.as_ref().unwrap(), &supported_langs)
+            },
+            _ => None,
+        },
+    };
+
+    // Maximum characters per run
+    let max_chars = match arg_struct.max_chars {
+        Some(max_chars) => max_chars,
+        None => configure::get_max_chars_per_run()?,
+    };
+
+    // Threshold of the warning for short inputs (disabled by --quiet)
+    let detect_threshold = if arg_struct.quiet {
+        0
+    } else {
+        arg_struct.source_lang_detect_threshold.unwrap_or(DEFAULT_SOURCE_LANG_DETECT_THRESHOLD)
+    };
+
+    // Optional parameters of the translation
+    let translate_options = TranslateOptions {
+        glossary_id: select_glossary_id(arg_struct.glossary_id, &source_lang, target_lang.as_ref().unwrap())?,
+        formality,
+        split_sentences: select_split_sentences(arg_struct.split_sentences, line_breaks),
+        concurrency: arg_struct.concurrency.unwrap_or(1),
+        extra_params: arg_struct.extra_params,
+        // The billed characters are reported by --summary
+        show_billed_characters: arg_struct.summary,
+    };
+
+    // DeepL API requires the source language with a glossary
+    if translate_options.glossary_id.is_some() && source_lang.is_none() {
+        return Err(RuntimeError::DeeplApiError(DpTranError::GlossaryRequiresSourceLang));
+    }
+
+    // Report the glossary to be applied (given by --glossary-id or configured for the language pair)
+    if arg_struct.show_glossary {
+        eprintln!("{}", glossary_report(&translate_options));
+    }
+
+    // Preload the cache with the phrases in the file
+    if mode == ExecutionMode::WarmCache {
+        let target_lang = target_lang.unwrap();
+        let warm_file = arg_struct.warm_file.unwrap_or_default();
+        let phrases = std::fs::read_to_string(&warm_file).map_err(|e| RuntimeError::FileIoError(e.to_string()))?;
+        let phrases = parse::normalize_line_endings(&phrases).lines().map(|line| line.to_string()).collect::<Vec<String>>();
+        let (added, cached) = warm_cache(&phrases, &source_lang, &target_lang, &cache_scope(&translate_options, None), |texts| {
+            check_max_chars(&texts, 0, max_chars)?;
+            let result = dptran::translate_with_options(&api_key, texts.clone(), &target_lang, &source_lang, &translate_options);
+            audit_translation(&texts, &source_lang, &target_lang, result.is_ok())?;
+            Ok(result?)
+        })?;
+        println!("{} phrases added to the cache, {} already cached.", added, cached);
+        return Ok(());
+    }
+
+    // Translation of the files in the directory
+    if mode == ExecutionMode::TranslateDirectory {
+        let target_lang = target_lang.unwrap();
+        let input_dir = arg_struct.input_dir.unwrap_or_default();
+        let output_dir = arg_struct.output_dir.unwrap_or_default();
+        // The maximum characters and the counters apply to the whole directory
+        let mut sent_chars = 0;
+        let mut summary = RunSummary::default();
+        let start = std::time::Instant::now();
+        let count = translate_directory(std::path::Path::new(&input_dir), std::path::Path::new(&output_dir), arg_struct.glob.as_deref(), |lines| {
+            sent_chars = check_max_chars(&lines, sent_chars, max_chars)?;
+            summary.add_api_call(&lines);
+            let result = dptran::translate_detailed(&api_key, lines.clone(), &target_lang, &source_lang, &translate_options);
+            audit_translation(&lines, &source_lang, &target_lang, result.is_ok())?;
+            let translations = result?;
+            summary.add_billed(&translations);
+            Ok(translations.into_iter().map(|translation| translation.text).collect())
+        })?;
+        println!("{} files translated into {}.", count, output_dir);
+        if arg_struct.summary {
+            eprintln!("{}", summary.report(start.elapsed()));
+        }
+        return Ok(());
+    }
+
     // (Dialogue &) Translation
-    process(&api_key, mode, source_lang, target_lang.unwrap(), 
-            arg_struct.multilines, arg_struct.remove_line_breaks, arg_struct.source_text, ofile)?;
+    let target_lang = target_lang.unwrap();
+    // Language whose lines are printed unchanged (--skip-same: the target language)
+    let skip_detected = if arg_struct.skip_same {
+        Some(target_lang.clone())
+    } else {
+        arg_struct.skip_if_detected
+    };
+    let start = std::time::Instant::now();
+    let run_options = RunOptions {
+        multilines: arg_struct.multilines,
+        once: arg_struct.interactive_once,
+        line_breaks,
+        input_filter,
+        keep_going: arg_struct.keep_going,
+        skip_detected,
+        show_source: arg_struct.show_source,
+        cache_override: arg_struct.cache_override,
+        max_chars,
+        detect_threshold,
+    };
+    let summary = process(&api_key, mode, source_lang.clone(), target_lang.clone(), run_options, translate_options, arg_struct.source_text, ofile)?;
+    if arg_struct.summary {
+        eprintln!("{}", summary.report(start.elapsed()));
+    }
+
+    remember_last_langs(&summary, source_lang, target_lang)?;
 
     Ok(())
 }
+
+/// Remember the languages of the translation, if enabled.
+/// Nothing is saved if no segment was translated (e.g. empty input).
+fn remember_last_langs(summary: &RunSummary, source_lang: Option<String>, target_lang: String) -> Result<(), RuntimeError> {
+    if summary.segments > 0 && configure::get_remember_last_langs()? {
+        configure::set_last_langs(source_lang, target_lang)?;
+    }
+    Ok(())
+}
+
+/// Lock for the tests pointing the endpoints of DeepL API at a local server
+#[cfg(test)]
+static ENDPOINT_TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Start a local server returning the responses in order, one per connection.
+/// Returns the URL of the server and the handle giving the received requests.
+#[cfg(test)]
+fn start_dummy_server(responses: Vec<String>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+    use std::io::Read;
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let mut requests = Vec::new();
+        for response in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+            // Read the headers and the body (Content-Length bytes)
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            loop {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                let complete = match text.find("\r\n\r\n") {
+                    Some(header_end) => {
+                        let length = text[..header_end].lines()
+                            .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap_or(0)))
+                            .unwrap_or(0);
+                        request.len() >= header_end + 4 + length
+                    },
+                    None => false,
+                };
+                if complete || n == 0 {
+                    break;
+                }
+            }
+            requests.push(String::from_utf8_lossy(&request).to_string());
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+        requests
+    });
+    (url, server)
+}
+
+/// Response of the dummy server.
+#[cfg(test)]
+fn dummy_response(status: &str, body: &str) -> String {
+    format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body)
+}
+
+/// Options of a translation run in the tests: translate as it is without the cache.
+#[cfg(test)]
+fn test_run_options() -> RunOptions {
+    RunOptions {
+        multilines: false,
+        once: false,
+        line_breaks: LineBreaks::Keep,
+        input_filter: InputFilter::default(),
+        keep_going: false,
+        skip_detected: None,
+        show_source: false,
+        cache_override: Some(false),
+        max_chars: 0,
+        detect_threshold: 0,
+    }
+}
+
+#[test]
+fn format_all_language_codes_test() {
+    let source_lang_codes = vec![("EN".to_string(), "English".to_string()), ("JA".to_string(), "Japanese".to_string())];
+    let target_lang_codes = vec![(("DE".to_string(), "German".to_string()), true), (("EN-US".to_string(), "English (American)".to_string()), false)];
+    let s = format_all_language_codes(&source_lang_codes, &target_lang_codes);
+    let source_pos = s.find("Source language codes:").expect("source header not found");
+    let target_pos = s.find("Target languages:").expect("target header not found");
+    assert!(source_pos < target_pos);
+    assert!(s.contains("EN-US: English (American)"));
+    assert!(s.contains("DE   : German *"));
+}
+
+#[test]
+fn expand_target_language_code_test() {
+    assert_eq!(expand_target_language_code("EN", true), "EN-US");
+    assert_eq!(expand_target_language_code("pt", true), "PT-PT");
+    assert_eq!(expand_target_language_code("EN-GB", true), "EN-GB");
+    assert_eq!(expand_target_language_code("EN", false), "EN");
+    assert_eq!(expand_target_language_code("PT", false), "PT");
+}
+
+#[test]
+fn doctor_checks_test() {
+    let _lock = ENDPOINT_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    // An error status is still an HTTP response, so the endpoint is reachable
+    let (url, server) = start_dummy_server(vec![dummy_response("403 Forbidden", ""), dummy_response("403 Forbidden", "")]);
+    dptran::set_translate_endpoint(Some(&format!("{}/v2/translate", url)));
+    dptran::set_usage_endpoint(Some(&format!("{}/v2/usage", url)));
+    // Nothing listens on the port of a dropped listener
+    let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    dptran::set_languages_endpoint(Some(&format!("http://{}/v2/languages", closed)));
+
+    let checks = doctor_checks(&None);
+    dptran::set_translate_endpoint(None);
+    dptran::set_usage_endpoint(None);
+    dptran::set_languages_endpoint(None);
+    let requests = server.join().unwrap();
+
+    // The overridden endpoints are checked
+    assert!(requests[0].starts_with("POST /v2/translate"));
+    assert!(requests[1].starts_with("POST /v2/usage"));
+    let translate_check = checks.iter().find(|c| c.name == "Translate endpoint is reachable").unwrap();
+    assert!(translate_check.passed);
+    assert!(translate_check.detail.contains("HTTP 403"));
+    assert!(checks.iter().find(|c| c.name == "Usage endpoint is reachable").unwrap().passed);
+    let languages_check = checks.iter().find(|c| c.name == "Languages endpoint is reachable").unwrap();
+    assert!(!languages_check.passed);
+    assert!(languages_check.detail.contains(&closed.to_string()));
+
+    // Without API key, the API key check fails and the validity check is skipped
+    let api_key_check = checks.iter().find(|c| c.name == "API key is set").unwrap();
+    assert!(!api_key_check.passed);
+    let validity_check = checks.iter().find(|c| c.name == "API key is valid").unwrap();
+    assert!(!validity_check.passed);
+    assert!(validity_check.detail.starts_with("skipped"));
+    assert!(checks.iter().any(|c| c.name == "Configuration file is writable"));
+}
+
+#[test]
+fn check_writable_test() {
+    let dir = std::env::temp_dir().join("dptran_check_writable_test");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    // A file in a missing directory: checked without creating anything
+    let path = dir.join("sub").join("config.json");
+    assert!(check_writable(&path).unwrap().ends_with("(will be created)"));
+    assert!(!dir.exists());
+
+    // An existing file: checked without modifying it
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.json");
+    std::fs::write(&path, "{}").unwrap();
+    assert!(check_writable(&path).is_ok());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}");
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn select_target_language_test() {
+    // The environment variable wins over the configured default
+    assert_eq!(select_target_language(None, Some("DE".to_string()), "EN".to_string()), "DE");
+    // -t wins over the environment variable
+    assert_eq!(select_target_language(Some("JA".to_string()), Some("DE".to_string()), "EN".to_string()), "JA");
+    // Empty or unset environment variable falls back to the configured default
+    assert_eq!(select_target_language(None, Some("".to_string()), "EN".to_string()), "EN");
+    assert_eq!(select_target_language(None, None, "EN".to_string()), "EN");
+}
+
+#[test]
+fn append_output_file_test() {
+    let output_file = std::env::temp_dir().join("dptran_append_output_file_test.txt");
+    let _ = std::fs::remove_file(&output_file);
+    let output_file = output_file.to_str().unwrap();
+
+    // Two runs with --append
+    for translated in ["first", "second"] {
+        let mut ofile = create_or_open_file(output_file, true).unwrap();
+        output_translated_texts(&ExecutionMode::TranslateNormal, vec![translated.to_string()], &mut ofile).unwrap();
+    }
+    assert_eq!(std::fs::read_to_string(output_file).unwrap(), "first\nsecond\n");
+    std::fs::remove_file(output_file).unwrap();
+}
+
+#[test]
+fn exit_code_test() {
+    assert_eq!(RuntimeError::DeeplApiError(DpTranError::ApiKeyIsNotSet).exit_code(), 3);
+    assert_eq!(RuntimeError::DeeplApiError(DpTranError::DeeplApiError(dptran::DeeplAPIError::ConnectionError(dptran::ConnectionError::Forbidden))).exit_code(), 4);
+    assert_eq!(RuntimeError::DeeplApiError(DpTranError::DeeplApiError(dptran::DeeplAPIError::LimitError)).exit_code(), 5);
+    assert_eq!(RuntimeError::ConfigError(ConfigError::FailToFixSettings).exit_code(), 6);
+    assert_eq!(RuntimeError::StdIoError("error".to_string()).exit_code(), 1);
+}
+
+#[test]
+fn runtime_exit_code_test() {
+    let _config_lock = configure::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _cache_lock = cache::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _endpoint_lock = ENDPOINT_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    configure::clear_settings().unwrap();
+
+    // 3: API key is not set
+    assert!(matches!(get_usage(), Err(ref e) if e.exit_code() == 3));
+
+    // 6: Configuration error
+    let settings_file = std::env::temp_dir().join("dptran_runtime_exit_code_test.json");
+    std::fs::write(&settings_file, "not json").unwrap();
+    let e = RuntimeError::from(configure::import_settings(&settings_file).unwrap_err());
+    std::fs::remove_file(&settings_file).unwrap();
+    assert_eq!(e.exit_code(), 6);
+
+    // 4: Connection error, 5: Translation limit reached, 1: Other errors (invalid response)
+    let (url, server) = start_dummy_server(vec![
+        dummy_response("403 Forbidden", ""),
+        dummy_response("456 Quota Exceeded", ""),
+        dummy_response("200 OK", "not json"),
+    ]);
+    dptran::set_translate_endpoint(Some(&format!("{}/v2/translate", url)));
+    let exit_codes = (0..3).map(|_| {
+        process(&"dummy".to_string(), ExecutionMode::TranslateNormal, Some("EN".to_string()), "JA".to_string(),
+            test_run_options(), TranslateOptions::default(), Some("Hello".to_string()), None).unwrap_err().exit_code()
+    }).collect::<Vec<i32>>();
+    dptran::set_translate_endpoint(None);
+    server.join().unwrap();
+    assert_eq!(exit_codes, vec![4, 5, 1]);
+}
+
+#[test]
+fn search_cache_log_test() {
+    use std::sync::Mutex;
+
+    // Logger capturing the log messages
+    struct CaptureLogger(Mutex<Vec<String>>);
+    impl log::Log for CaptureLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool { true }
+        fn log(&self, record: &log::Record) {
+            self.0.lock().unwrap().push(record.args().to_string());
+        }
+        fn flush(&self) {}
+    }
+    static LOGGER: CaptureLogger = CaptureLogger(Mutex::new(Vec::new()));
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Debug);
+    let _lock = cache::TEST_LOCK.lock().unwrap();
+
+    let text = "search_cache_log_test".to_string();
+    let source_lang = Some("EN".to_string());
+    let target_lang = "JA".to_string();
+    // The first translation is a miss, and the repeated one is a hit
+    if search_cache(&text, &source_lang, &target_lang, "").unwrap().is_none() {
+        cache::into_cache_element(&text, "translated", &source_lang, &target_lang, "", 100).unwrap();
+    }
+    assert_eq!(search_cache(&text, &source_lang, &target_lang, "").unwrap(), Some("translated".to_string()));
+
+    let logs = LOGGER.0.lock().unwrap();
+    let log = logs.last().unwrap();
+    assert!(log.starts_with("cache hit: EN -> JA (key: "));
+    // The key is the md5 hash of the entry
+    let key = log.trim_start_matches("cache hit: EN -> JA (key: ").trim_end_matches(')');
+    assert_eq!(key.len(), 32);
+    assert!(key.chars().all(|c| c.is_ascii_hexdigit()));
+}
+
+#[test]
+fn limit_error_message_test() {
+    let usage = DpTranUsage {
+        character_count: 500000,
+        character_limit: 500000,
+        unlimited: false,
+        document_count: None,
+        document_limit: None,
+    };
+    let message = limit_error_message(&Some(usage));
+    assert!(message.contains("translation limit of your account has been reached"));
+    assert!(message.contains("usage: 500000 / 500000"));
+    assert!(message.contains("upgrading"));
+    // Usage could not be fetched
+    let message = limit_error_message(&None);
+    assert!(!message.contains("usage:"));
+}
+
+#[test]
+fn limit_error_test() {
+    let _config_lock = configure::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _cache_lock = cache::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _endpoint_lock = ENDPOINT_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    configure::clear_settings().unwrap();
+    configure::set_api_key("dummy".to_string()).unwrap();
+    // 456 for the translation, then the usage shown in the message
+    let (url, server) = start_dummy_server(vec![
+        dummy_response("456 Quota Exceeded", r#"{"message":"Quota Exceeded"}"#),
+        dummy_response("200 OK", r#"{"character_count":500000,"character_limit":500000}"#),
+    ]);
+    dptran::set_translate_endpoint(Some(&format!("{}/v2/translate", url)));
+    dptran::set_usage_endpoint(Some(&format!("{}/v2/usage", url)));
+
+    let e = process(&"dummy".to_string(), ExecutionMode::TranslateNormal, Some("EN".to_string()), "JA".to_string(),
+        test_run_options(), TranslateOptions::default(), Some("Hello".to_string()), None).unwrap_err();
+    let message = error_message(&e);
+    dptran::set_translate_endpoint(None);
+    dptran::set_usage_endpoint(None);
+    let requests = server.join().unwrap();
+    configure::clear_settings().unwrap();
+
+    assert!(matches!(e, RuntimeError::DeeplApiError(DpTranError::DeeplApiError(dptran::DeeplAPIError::LimitError))));
+    assert_eq!(e.exit_code(), 5);
+    assert!(message.starts_with("Error: The translation limit of your account has been reached."));
+    assert!(message.contains("usage: 500000 / 500000"));
+    assert!(requests[1].starts_with("POST /v2/usage"));
+}
+
+#[test]
+fn read_stream_line_test() {
+    let mut reader = io::Cursor::new("first line\r\nsecond line\n\nthird line");
+    assert_eq!(read_stream_line(&mut reader), Some("first line".to_string()));
+    assert_eq!(read_stream_line(&mut reader), Some("second line".to_string()));
+    assert_eq!(read_stream_line(&mut reader), Some("".to_string()));
+    assert_eq!(read_stream_line(&mut reader), Some("third line".to_string()));
+    assert_eq!(read_stream_line(&mut reader), None);
+}
+
+#[test]
+fn keep_detected_lines_test() {
+    // EN input with --skip-same -t EN-US is echoed verbatim
+    let input = vec!["Hello, world!".to_string(), "こんにちは".to_string()];
+    let translations = vec![
+        DpTranTranslation { text: "Hello world!".to_string(), detected_source_language: Some("EN".to_string()), billed_characters: None },
+        DpTranTranslation { text: "Hello".to_string(), detected_source_language: Some("JA".to_string()), billed_characters: None },
+    ];
+    assert_eq!(keep_detected_lines(&input, translations, "EN-US"), vec!["Hello, world!".to_string(), "Hello".to_string()]);
+    assert!(is_same_language("EN", "en-gb"));
+    assert!(!is_same_language("PT", "EN"));
+}
+
+#[test]
+fn exits_after_translation_test() {
+    assert!(exits_after_translation(&ExecutionMode::TranslateNormal, false));
+    assert!(!exits_after_translation(&ExecutionMode::TranslateInteractive, false));
+    assert!(exits_after_translation(&ExecutionMode::TranslateInteractive, true));
+    assert!(!exits_after_translation(&ExecutionMode::TranslateStream, false));
+}
+
+#[test]
+fn run_summary_test() {
+    let mut summary = RunSummary::default();
+    summary.add_api_call(&["Hello".to_string(), "world".to_string()]);
+    summary.add_billed(&[
+        DpTranTranslation { text: "こんにちは".to_string(), detected_source_language: Some("EN".to_string()), billed_characters: Some(5) },
+        DpTranTranslation { text: "世界".to_string(), detected_source_language: Some("EN".to_string()), billed_characters: Some(5) },
+    ]);
+    summary.add_cache_hit(2);
+    assert_eq!(summary, RunSummary { segments: 4, cache_hits: 1, api_calls: 1, chars_billed: 10 });
+    assert_eq!(summary.report(std::time::Duration::from_millis(1500)), "Summary: 4 segments, 1 cache hits, 1 API calls, 10 characters billed, 1.50s elapsed");
+
+    // One fresh translation and one cached translation
+    let _config_lock = configure::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _cache_lock = cache::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _endpoint_lock = ENDPOINT_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    configure::clear_settings().unwrap();
+    cache::clear_cache().unwrap();
+    let (url, server) = start_dummy_server(vec![
+        dummy_response("200 OK", r#"{"translations":[{"detected_source_language":"EN","text":"こんにちは","billed_characters":5}]}"#),
+    ]);
+    dptran::set_translate_endpoint(Some(&format!("{}/v2/translate", url)));
+    let run_options = RunOptions { cache_override: Some(true), ..test_run_options() };
+    let translate_options = TranslateOptions::new().with_show_billed_characters(true);
+    let run = || process(&"dummy".to_string(), ExecutionMode::TranslateNormal, Some("EN".to_string()), "JA".to_string(),
+        run_options.clone(), translate_options.clone(), Some("Hello".to_string()), None);
+    let fresh = run();
+    let cached = run();
+    dptran::set_translate_endpoint(None);
+    let requests = server.join().unwrap();
+    cache::clear_cache().unwrap();
+
+    assert_eq!(fresh.unwrap(), RunSummary { segments: 1, cache_hits: 0, api_calls: 1, chars_billed: 5 });
+    assert_eq!(cached.unwrap(), RunSummary { segments: 1, cache_hits: 1, api_calls: 0, chars_billed: 0 });
+    assert!(requests[0].contains("show_billed_characters=1"));
+}
+
+#[test]
+fn remember_last_langs_test() {
+    let _config_lock = configure::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _cache_lock = cache::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _endpoint_lock = ENDPOINT_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    configure::clear_settings().unwrap();
+    cache::clear_cache().unwrap();
+    configure::set_remember_last_langs(true).unwrap();
+    let (url, server) = start_dummy_server(vec![
+        dummy_response("200 OK", r#"{"translations":[{"detected_source_language":"EN","text":"Hallo"}]}"#),
+    ]);
+    dptran::set_translate_endpoint(Some(&format!("{}/v2/translate", url)));
+    let run = |target_lang: String, text: &str| {
+        let summary = process(&"dummy".to_string(), ExecutionMode::TranslateNormal, Some("EN".to_string()), target_lang.clone(),
+            test_run_options(), TranslateOptions::default(), Some(text.to_string()), None).unwrap();
+        remember_last_langs(&summary, Some("EN".to_string()), target_lang)
+    };
+
+    // A DE run, then a run without -t reuses DE
+    run(select_target_language(Some("DE".to_string()), None, configure::get_fallback_target_language_code().unwrap()), "Hello").unwrap();
+    let target_lang = select_target_language(None, None, configure::get_fallback_target_language_code().unwrap());
+    assert_eq!(target_lang, "DE");
+
+    // A run translating nothing is not remembered
+    run("FR".to_string(), "   ").unwrap();
+    dptran::set_translate_endpoint(None);
+    server.join().unwrap();
+    assert_eq!(configure::get_last_langs().unwrap(), (Some("EN".to_string()), Some("DE".to_string())));
+    assert_eq!(select_target_language(None, None, configure::get_fallback_target_language_code().unwrap()), "DE");
+
+    configure::clear_settings().unwrap();
+}
+
+#[test]
+fn count_chars_test() {
+    let text = Some("héllo".to_string());
+    let input = get_input(&ExecutionMode::TranslateNormal, false, false, InputFilter::default(), &text).unwrap();
+    assert_eq!(count_chars(&input), 5);
+    assert_eq!(count_chars(&[]), 0);
+}
+
+#[test]
+fn check_max_chars_test() {
+    let input = vec!["Hello".to_string(), "World!".to_string()];
+    // Under the cap
+    assert_eq!(check_max_chars(&input, 0, 20).unwrap(), 11);
+    // Exceeding the cap including the characters already sent in this run
+    match check_max_chars(&input, 10, 20) {
+        Err(RuntimeError::MaxCharsExceeded(requested, allowed)) => {
+            assert_eq!(requested, 21);
+            assert_eq!(allowed, 20);
+        },
+        _ => panic!("Error: the cap is not applied"),
+    }
+    // Unlimited
+    assert_eq!(check_max_chars(&input, 0, 0).unwrap(), 11);
+
+    // The run exceeding the cap sends no request
+    let _config_lock = configure::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _cache_lock = cache::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _endpoint_lock = ENDPOINT_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    configure::clear_settings().unwrap();
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    dptran::set_translate_endpoint(Some(&format!("http://{}/v2/translate", listener.local_addr().unwrap())));
+    let run_options = RunOptions { max_chars: 5, ..test_run_options() };
+    let result = process(&"dummy".to_string(), ExecutionMode::TranslateNormal, Some("EN".to_string()), "JA".to_string(),
+        run_options, TranslateOptions::default(), Some("Hello world".to_string()), None);
+    dptran::set_translate_endpoint(None);
+    assert!(matches!(result, Err(RuntimeError::MaxCharsExceeded(11, 5))));
+    let accepted = listener.accept();
+    assert!(matches!(accepted, Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock), "a request was sent: {:?}", accepted);
+}
+
+#[test]
+fn interleave_source_test() {
+    let source = vec!["Hello.".to_string(), "Good morning.".to_string()];
+    let translated = vec!["こんにちは。".to_string(), "おはようございます。".to_string()];
+    let output = interleave_source(&source, translated);
+    assert_eq!(output, vec!["Hello.", "こんにちは。", "Good morning.", "おはようございます。"]);
+
+    // Cached translation is a single text
+    let translated = vec!["こんにちは。\nおはようございます。".to_string()];
+    let output = interleave_source(&source, translated);
+    assert_eq!(output, vec!["Hello.\nGood morning.", "こんにちは。\nおはようございます。"]);
+}
+
+#[test]
+fn runtime_error_from_test() {
+    fn propagate_config_error() -> Result<(), RuntimeError> {
+        Err(ConfigError::FailToGetSettings("test".to_string()))?
+    }
+    fn propagate_cache_error() -> Result<(), RuntimeError> {
+        Err(CacheError::FailToReadCache("test".to_string()))?
+    }
+    fn propagate_dptran_error() -> Result<(), RuntimeError> {
+        Err(DpTranError::ApiKeyIsNotSet)?
+    }
+    assert!(matches!(propagate_config_error(), Err(RuntimeError::ConfigError(_))));
+    assert!(matches!(propagate_cache_error(), Err(RuntimeError::CacheError(_))));
+    assert!(matches!(propagate_dptran_error(), Err(RuntimeError::DeeplApiError(DpTranError::ApiKeyIsNotSet))));
+}
+
+#[test]
+fn short_input_warning_test() {
+    let short = vec!["Ja".to_string()];
+    let long = vec!["This sentence is long enough.".to_string()];
+    assert!(short_input_warning(&short, &None, DEFAULT_SOURCE_LANG_DETECT_THRESHOLD).is_some());
+    assert!(short_input_warning(&long, &None, DEFAULT_SOURCE_LANG_DETECT_THRESHOLD).is_none());
+    // No warning if the source language is specified or the warning is disabled
+    assert!(short_input_warning(&short, &Some("DE".to_string()), DEFAULT_SOURCE_LANG_DETECT_THRESHOLD).is_none());
+    assert!(short_input_warning(&short, &None, 0).is_none());
+}
+
+#[test]
+fn use_cache_test() {
+    assert!(use_cache(true, None));
+    assert!(!use_cache(false, None));
+    // --no-cache
+    assert!(!use_cache(true, Some(false)));
+    // --cache
+    assert!(use_cache(false, Some(true)));
+}
+
+#[test]
+fn cache_scope_test() {
+    // No scope without parameters, so that the existing entries are still hit
+    assert_eq!(cache_scope(&TranslateOptions::new().with_concurrency(4), None), "");
+    let options = TranslateOptions::new()
+        .with_glossary_id("def3a26b-3e84-45b3-84ae-0c0aaf3525f7")
+        .with_formality(Formality::More)
+        .with_param("model_type", "quality_optimized");
+    assert_eq!(cache_scope(&options, None), "glossary=def3a26b-3e84-45b3-84ae-0c0aaf3525f7&formality=more&model_type=quality_optimized");
+
+    // A glossary translation is not returned for the lookup without the glossary
+    let _lock = cache::TEST_LOCK.lock().unwrap();
+    let text = "cache_scope_test".to_string();
+    let target_lang = "JA".to_string();
+    let glossary_scope = cache_scope(&TranslateOptions::new().with_glossary_id("def3a26b-3e84-45b3-84ae-0c0aaf3525f7"), None);
+    cache::into_cache_element(&text, "with glossary", &None, &target_lang, &glossary_scope, 100).unwrap();
+    assert_eq!(search_cache(&text, &None, &target_lang, &glossary_scope).unwrap(), Some("with glossary".to_string()));
+    assert_eq!(search_cache(&text, &None, &target_lang, &cache_scope(&TranslateOptions::new(), None)).unwrap(), None);
+
+    // A result with the skipped lines is not returned for the plain lookup
+    let text = "cache_scope_test skip".to_string();
+    let skip_scope = cache_scope(&TranslateOptions::new(), Some("ja"));
+    assert_eq!(skip_scope, "skip_detected=JA");
+    cache::into_cache_element(&text, "cache_scope_test skip", &None, &target_lang, &skip_scope, 100).unwrap();
+    assert_eq!(search_cache(&text, &None, &target_lang, &skip_scope).unwrap(), Some("cache_scope_test skip".to_string()));
+    assert_eq!(search_cache(&text, &None, &target_lang, &cache_scope(&TranslateOptions::new(), None)).unwrap(), None);
+}
+
+#[test]
+fn check_formality_test() {
+    let supported_langs = vec!["DE".to_string(), "FR".to_string(), "JA".to_string()];
+    // EN does not support formality
+    match check_formality(Formality::More, "EN", &supported_langs, false) {
+        Err(RuntimeError::FormalityNotSupported(target_lang, langs)) => {
+            assert_eq!(target_lang, "EN");
+            assert_eq!(langs, supported_langs);
+        },
+        _ => panic!("Error: formality is accepted for EN"),
+    }
+    assert_eq!(check_formality(Formality::Less, "ja", &supported_langs, false).ok(), Some(Formality::Less));
+    assert_eq!(check_formality(Formality::Default, "EN", &supported_langs, false).ok(), Some(Formality::Default));
+    // prefer_* is accepted for any language
+    assert_eq!(check_formality(Formality::PreferMore, "EN", &supported_langs, false).ok(), Some(Formality::PreferMore));
+    // Auto-substitution with --formality-fallback
+    assert_eq!(check_formality(Formality::More, "EN", &supported_langs, true).ok(), Some(Formality::PreferMore));
+    assert_eq!(check_formality(Formality::Less, "EN", &supported_langs, true).ok(), Some(Formality::PreferLess));
+    assert_eq!(check_formality(Formality::More, "DE", &supported_langs, true).ok(), Some(Formality::More));
+}
+
+#[test]
+fn info_lines_test() {
+    let _lock = cache::TEST_LOCK.lock().unwrap();
+    let info = get_info(&Some("secret-api-key:fx".to_string())).unwrap();
+    let lines = info_lines(&info);
+    assert!(lines.contains(&format!("dptran version: {}", env!("CARGO_PKG_VERSION"))));
+    assert!(lines.contains(&"API key: set (free)".to_string()));
+    let config_filepath = configure::get_config_file_path().unwrap();
+    assert!(lines.contains(&format!("Configuration file path: {}", config_filepath.display())));
+    // The API key is not printed
+    assert!(lines.iter().all(|line| !line.contains("secret-api-key")));
+}
+
+#[test]
+fn info_json_test() {
+    let _lock = cache::TEST_LOCK.lock().unwrap();
+    let info = get_info(&None).unwrap();
+    let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&info).unwrap()).unwrap();
+    assert_eq!(json["api_key"], "not set");
+    assert_eq!(json["endpoints"]["translate"], dptran::DEEPL_API_TRANSLATE);
+    assert_eq!(json["endpoints"]["usage"], dptran::DEEPL_API_USAGE);
+    assert_eq!(json["endpoints"]["languages"], dptran::DEEPL_API_LANGUAGES);
+}
+
+#[test]
+fn apply_default_formality_test() {
+    let supported_langs = vec!["DE".to_string(), "JA".to_string()];
+    assert_eq!(apply_default_formality(Formality::More, "DE", &supported_langs), Some(Formality::More));
+    assert_eq!(apply_default_formality(Formality::Less, "ja", &supported_langs), Some(Formality::Less));
+    // Skipped for unsupported languages
+    assert_eq!(apply_default_formality(Formality::More, "EN-US", &supported_langs), None);
+}
+
+#[test]
+fn select_glossary_id_test() {
+    assert_eq!(parse_language_pair("EN:JA").unwrap(), ("EN".to_string(), "JA".to_string()));
+    assert!(parse_language_pair("EN").is_err());
+    assert!(parse_language_pair(":JA").is_err());
+
+    // --glossary-id wins
+    let glossary_id = Some("def3a26b-3e84-45b3-84ae-0c0aaf3525f7".to_string());
+    assert_eq!(select_glossary_id(glossary_id.clone(), &Some("EN".to_string()), "JA").unwrap(), glossary_id);
+    // No glossary for the detected source language
+    assert_eq!(select_glossary_id(None, &None, "JA").unwrap(), None);
+}
+
+#[test]
+fn glossary_report_test() {
+    let _lock = configure::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let options = TranslateOptions { glossary_id: Some("def3a26b-3e84-45b3-84ae-0c0aaf3525f7".to_string()), ..Default::default() };
+    assert_eq!(glossary_report(&options), "Glossary applied: def3a26b-3e84-45b3-84ae-0c0aaf3525f7");
+    assert_eq!(glossary_report(&TranslateOptions::default()), "No glossary applied");
+
+    // The glossary configured for the language pair is reported without --glossary-id
+    configure::clear_settings().unwrap();
+    configure::set_glossary_for("EN", "JA", Some("0f5b6a7c-1d2e-4f30-9a8b-7c6d5e4f3a2b".to_string())).unwrap();
+    let options = TranslateOptions { glossary_id: select_glossary_id(None, &Some("EN".to_string()), "JA").unwrap(), ..Default::default() };
+    assert_eq!(glossary_report(&options), "Glossary applied: 0f5b6a7c-1d2e-4f30-9a8b-7c6d5e4f3a2b");
+    // Not applied to the other pairs
+    let options = TranslateOptions { glossary_id: select_glossary_id(None, &Some("EN".to_string()), "DE").unwrap(), ..Default::default() };
+    assert_eq!(glossary_report(&options), "No glossary applied");
+    configure::clear_settings().unwrap();
+}
+
+#[test]
+fn format_usage_test() {
+    let mut usage = DpTranUsage {
+        character_count: 1000,
+        character_limit: 500000,
+        unlimited: false,
+        document_count: Some(2),
+        document_limit: Some(10),
+    };
+    let s = format_usage(&usage);
+    assert!(s.contains("usage: 1000 / 500000 (0%)"));
+    assert!(s.contains("remaining: 499000"));
+    assert!(s.contains("document usage: 2 / 10"));
+    // Not displayed if the API does not return the document usage
+    usage.document_count = None;
+    usage.document_limit = None;
+    assert!(!format_usage(&usage).contains("document usage"));
+}
+
+#[test]
+fn build_info_test() {
+    let info = build_info();
+    assert!(info.starts_with(&format!("dptran {}\n", env!("CARGO_PKG_VERSION"))));
+    assert!(info.contains("target: "));
+    assert!(info.contains("rustc: "));
+    assert!(info.contains("features: "));
+}
+
+#[test]
+fn dormant_key_hint_test() {
+    let e = RuntimeError::DeeplApiError(DpTranError::DeeplApiError(dptran::DeeplAPIError::ConnectionError(dptran::ConnectionError::Forbidden)));
+    let message = e.to_string();
+    assert!(message.starts_with("403 Forbidden Error."));
+    assert!(message.ends_with(DORMANT_KEY_HINT));
+
+    // A server returning 403
+    let _config_lock = configure::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _cache_lock = cache::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let _endpoint_lock = ENDPOINT_TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    configure::clear_settings().unwrap();
+    let (url, server) = start_dummy_server(vec![dummy_response("403 Forbidden", "")]);
+    dptran::set_translate_endpoint(Some(&format!("{}/v2/translate", url)));
+    let e = process(&"dummy".to_string(), ExecutionMode::TranslateNormal, Some("EN".to_string()), "JA".to_string(),
+        test_run_options(), TranslateOptions::default(), Some("Hello".to_string()), None).unwrap_err();
+    dptran::set_translate_endpoint(None);
+    server.join().unwrap();
+    assert!(error_message(&e).contains(DORMANT_KEY_HINT));
+    assert_eq!(e.exit_code(), 4);
+}
+
+#[test]
+fn reflow_test() {
+    let lines = ["This is the first", "paragraph.", "", "", "Second paragraph."].iter().map(|x| x.to_string()).collect::<Vec<String>>();
+    let paragraphs = reflow_paragraphs(&lines);
+    assert_eq!(paragraphs, vec!["This is the first paragraph.", "Second paragraph."]);
+    // The paragraphs are kept in the output
+    assert_eq!(separate_paragraphs(vec!["最初の段落です。".to_string(), "2番目の段落。".to_string()]), vec!["最初の段落です。", "", "2番目の段落。"]);
+
+    // nonewlines is used in the reflow mode unless specified
+    assert_eq!(select_split_sentences(None, LineBreaks::Reflow), Some(SplitSentences::NoNewlines));
+    assert_eq!(select_split_sentences(Some(SplitSentences::On), LineBreaks::Reflow), Some(SplitSentences::On));
+    assert_eq!(select_split_sentences(None, LineBreaks::Keep), None);
+}
+
+#[test]
+fn filter_lines_test() {
+    let lines = ["  Hello, world!  ", "", "   ", "\tGood morning."].iter().map(|x| x.to_string()).collect::<Vec<String>>();
+
+    // No clean-up by default
+    assert_eq!(filter_lines(lines.clone(), InputFilter::default()), lines);
+    // --trim
+    let filter = InputFilter { trim: true, collapse_blank_lines: false };
+    assert_eq!(filter_lines(lines.clone(), filter), vec!["Hello, world!", "", "", "Good morning."]);
+    // --collapse-blank-lines
+    let filter = InputFilter { trim: false, collapse_blank_lines: true };
+    assert_eq!(filter_lines(lines.clone(), filter), vec!["  Hello, world!  ", "\tGood morning."]);
+    // Both
+    let filter = InputFilter { trim: true, collapse_blank_lines: true };
+    assert_eq!(filter_lines(lines.clone(), filter), vec!["Hello, world!", "Good morning."]);
+    // Normal mode
+    let text = Some("  Hello, world!\n\n  Good morning.  ".to_string());
+    assert_eq!(get_input(&ExecutionMode::TranslateNormal, false, false, filter, &text), Some(vec!["Hello, world!".to_string(), "Good morning.".to_string()]));
+    assert_eq!(get_input(&ExecutionMode::TranslateNormal, false, true, filter, &text), Some(vec!["Hello, world! Good morning.".to_string()]));
+}
+
+#[test]
+fn settings_json_test() {
+    let settings = get_settings().unwrap();
+    let json: serde_json::Value = serde_json::from_str(&serde_json::to_string(&settings).unwrap()).unwrap();
+    assert!(json["default_target_language"].is_string());
+    assert!(json["cache_max_entries"].is_u64());
+    assert!(json["cache_enabled"].is_boolean());
+    assert!(json["config_file_path"].as_str().unwrap().contains("configure_test"));
+    // The API key is not included
+    assert!(json.get("api_key").is_none());
+}
+
+#[test]
+fn crlf_input_test() {
+    let crlf = Some("Hello,\r\nworld!\rGood morning.\r\n".to_string());
+    let lf = Some("Hello,\nworld!\nGood morning.\n".to_string());
+    let input = get_input(&ExecutionMode::TranslateNormal, false, false, InputFilter::default(), &crlf).unwrap();
+    assert_eq!(input, vec!["Hello,", "world!", "Good morning."]);
+    assert!(input.iter().all(|line| !line.contains('\r')));
+    let input = get_input(&ExecutionMode::TranslateNormal, false, true, InputFilter::default(), &crlf).unwrap();
+    assert_eq!(input, vec!["Hello, world! Good morning."]);
+
+    // The cache key does not depend on the line endings
+    let crlf_input = get_input(&ExecutionMode::TranslateNormal, false, false, InputFilter::default(), &crlf).unwrap();
+    let lf_input = get_input(&ExecutionMode::TranslateNormal, false, false, InputFilter::default(), &lf).unwrap();
+    let target_lang = "JA".to_string();
+    assert_eq!(cache::cache_hash(&crlf_input.join("\n"), &None, &target_lang, ""), cache::cache_hash(&lf_input.join("\n"), &None, &target_lang, ""));
+}
+
+#[test]
+fn translate_directory_test() {
+    let root = std::env::temp_dir().join(format!("dptran_translate_directory_test_{}", std::process::id()));
+    let input_dir = root.join("docs");
+    let output_dir = root.join("docs_ja");
+    std::fs::create_dir_all(input_dir.join("guide")).unwrap();
+    std::fs::write(input_dir.join("README.md"), "# Title\n\nHello.\n").unwrap();
+    std::fs::write(input_dir.join("guide").join("usage.txt"), "Usage\r\n").unwrap();
+    std::fs::write(input_dir.join("logo.png"), [0x89, 0x50, 0x4e, 0x47, 0x00, 0xff]).unwrap();
+
+    let translate = |lines: Vec<String>| Ok(lines.iter().map(|line| format!("[JA] {}", line)).collect());
+    let count = translate_directory(&input_dir, &output_dir, None, translate).unwrap();
+    assert_eq!(count, 2);
+    assert_eq!(std::fs::read_to_string(output_dir.join("README.md")).unwrap(), "[JA] # Title\n\n[JA] Hello.\n");
+    assert_eq!(std::fs::read_to_string(output_dir.join("guide").join("usage.txt")).unwrap(), "[JA] Usage\n");
+    // Binary files are skipped
+    assert!(!output_dir.join("logo.png").exists());
+
+    // Only the files matching the glob pattern
+    std::fs::remove_dir_all(&output_dir).unwrap();
+    let count = translate_directory(&input_dir, &output_dir, Some("*.md"), translate).unwrap();
+    assert_eq!(count, 1);
+    assert!(output_dir.join("README.md").exists());
+    assert!(!output_dir.join("guide").join("usage.txt").exists());
+
+    // The maximum characters are checked across the files: the second file exceeds the limit
+    std::fs::remove_dir_all(&output_dir).unwrap();
+    std::fs::write(input_dir.join("blank.txt"), "\n\n").unwrap();
+    let mut sent_chars = 0;
+    let mut calls = 0;
+    let res = translate_directory(&input_dir, &output_dir, None, |lines| {
+        sent_chars = check_max_chars(&lines, sent_chars, 15)?;
+        calls += 1;
+        translate(lines)
+    });
+    assert!(matches!(res, Err(RuntimeError::MaxCharsExceeded(18, 15))));
+    // The blank file is not sent
+    assert_eq!(calls, 1);
+    assert!(output_dir.join("blank.txt").exists());
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn warm_cache_test() {
+    let _lock = cache::TEST_LOCK.lock().unwrap();
+    let phrases = vec!["warm_cache_test: Good morning.".to_string(), "".to_string(), "warm_cache_test: Thank you.".to_string()];
+    let source_lang = Some("EN".to_string());
+    let target_lang = "JA".to_string();
+    cache::clear_cache().unwrap();
+
+    let translate = |texts: Vec<String>| Ok(texts.iter().map(|text| format!("[JA] {}", text)).collect());
+    assert_eq!(warm_cache(&phrases, &source_lang, &target_lang, "", translate).unwrap(), (2, 0));
+    // Both phrases are cache hits afterwards
+    assert_eq!(search_cache("warm_cache_test: Good morning.", &source_lang, &target_lang, "").unwrap(), Some("[JA] warm_cache_test: Good morning.".to_string()));
+    assert_eq!(search_cache("warm_cache_test: Thank you.", &source_lang, &target_lang, "").unwrap(), Some("[JA] warm_cache_test: Thank you.".to_string()));
+    // Nothing is translated again
+    let fail = |_: Vec<String>| Err(RuntimeError::StdIoError("translated again".to_string()));
+    assert_eq!(warm_cache(&phrases, &source_lang, &target_lang, "", fail).unwrap(), (0, 2));
+
+    // Nothing is sent nor stored if the phrases exceed the maximum characters
+    let phrases = vec!["warm_cache_test: See you.".to_string()];
+    let limited = |texts: Vec<String>| {
+        check_max_chars(&texts, 0, 10)?;
+        translate(texts)
+    };
+    assert!(matches!(warm_cache(&phrases, &source_lang, &target_lang, "", limited), Err(RuntimeError::MaxCharsExceeded(25, 10))));
+    assert_eq!(search_cache(&phrases[0], &source_lang, &target_lang, "").unwrap(), None);
+}
+
+#[test]
+fn plain_test() {
+    let filter = InputFilter { trim: true, collapse_blank_lines: true };
+    let (line_breaks, filter) = preprocessing(true, LineBreaks::Reflow, filter);
+    assert_eq!(line_breaks, LineBreaks::Keep);
+    // The markup and the whitespace are sent as they are
+    let text = Some("# Title\n\n    let x = 1;  \n".to_string());
+    assert_eq!(get_input(&ExecutionMode::TranslateNormal, false, line_breaks == LineBreaks::Remove, filter, &text),
+        Some(vec!["# Title".to_string(), "".to_string(), "    let x = 1;  ".to_string()]));
+    assert_eq!(select_split_sentences(None, line_breaks), None);
+
+    // Without --plain, the preprocessing is kept
+    let (line_breaks, filter) = preprocessing(false, LineBreaks::Reflow, InputFilter { trim: true, collapse_blank_lines: false });
+    assert_eq!(line_breaks, LineBreaks::Reflow);
+    assert!(filter.trim);
+}
+
+#[test]
+fn older_than_secs_test() {
+    assert_eq!(older_than_secs(0), 0);
+    assert_eq!(older_than_secs(30), 30 * 24 * 60 * 60);
+    assert_eq!(older_than_secs(u64::MAX), u64::MAX);
+}
+
+#[test]
+fn formality_language_codes_test() {
+    let target_lang_codes = vec![
+        (("DE".to_string(), "German".to_string()), true),
+        (("EN-US".to_string(), "English (American)".to_string()), false),
+        (("JA".to_string(), "Japanese".to_string()), true),
+    ];
+    assert_eq!(formality_language_codes(target_lang_codes), vec!["DE".to_string(), "JA".to_string()]);
+}
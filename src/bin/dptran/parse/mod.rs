@@ -1,26 +1,56 @@
-use clap::{ArgGroup, Parser, Subcommand};
-use std::io::{self, Read};
+use clap::{ArgGroup, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use std::io::{self, Read, Write};
 use atty::Stream;
 use super::RuntimeError;
 use std::process::Command;
 use super::configure;
+use super::style::ColorChoice;
+use dptran::{Formality, SplitSentences};
+
+/// Default delay in milliseconds before the first retry.
+const DEFAULT_RETRY_DELAY_MS: u64 = 1000;
 
 #[derive(PartialEq, Debug, Clone, Copy)]
 pub enum ExecutionMode {
     TranslateNormal,
     TranslateInteractive,
+    TranslateStream,
+    TranslateDirectory,
     ListSourceLangs,
     ListTargetLangs,
+    ListAllLangs,
     SetApiKey,
     SetDefaultTargetLang,
+    ResetDefaultTargetLang,
     SetCacheMaxEntries,
     SetEditor,
+    SetLegacyLangExpansion,
+    SetMaxCharsPerRun,
+    SetDefaultFormality,
+    SetAuditLogPath,
+    SetCacheNormalizeWhitespace,
+    SetRememberLastLangs,
+    SetGlossaryFor,
+    ExportSettings,
+    ImportSettings,
     DisplaySettings,
     EnableCache,
     DisableCache,
     ClearCache,
+    WarmCache,
+    ExportCache,
+    ImportCache,
+    PruneCache,
+    ListCache,
     ClearSettings,
     PrintUsage,
+    PrintBuildInfo,
+    Doctor,
+    Ping,
+    Info,
+    GenerateCompletions,
+    GenerateManPage,
 }
 
 #[derive(Clone, Debug)]
@@ -30,12 +60,58 @@ pub struct ArgStruct {
     pub default_target_lang: Option<String>,
     pub cache_max_entries: Option<usize>,
     pub editor_command: Option<String>,
+    pub legacy_lang_expansion: Option<bool>,
+    pub max_chars_per_run: Option<usize>,
+    pub default_formality: Option<Formality>,
+    pub audit_log_path: Option<String>,
+    pub cache_normalize_whitespace: Option<bool>,
+    pub remember_last_langs: Option<bool>,
+    pub glossary_for: Option<(String, String)>,
+    pub completion_shell: Option<Shell>,
+    pub json: bool,
     pub translate_from: Option<String>,
     pub multilines: bool,
     pub remove_line_breaks: bool,
+    pub reflow: bool,
+    pub trim: bool,
+    pub collapse_blank_lines: bool,
+    pub plain: bool,
+    pub count_only: bool,
+    pub summary: bool,
+    pub interactive_once: bool,
+    pub split_sentences: Option<SplitSentences>,
+    pub keep_going: bool,
+    pub skip_same: bool,
+    pub skip_if_detected: Option<String>,
+    pub show_source: bool,
+    pub show_glossary: bool,
+    pub cache_override: Option<bool>,
+    pub max_chars: Option<usize>,
+    pub glossary_id: Option<String>,
+    pub formality: Option<Formality>,
+    pub formality_fallback: bool,
+    pub concurrency: Option<usize>,
+    pub connect_timeout: Option<u64>,
+    pub request_timeout: Option<u64>,
+    pub max_retries: u32,
+    pub retry_delay: u64,
+    pub extra_params: Vec<(String, String)>,
     pub translate_to: Option<String>,
     pub source_text: Option<String>,
     pub ofile_path: Option<String>,
+    pub input_dir: Option<String>,
+    pub output_dir: Option<String>,
+    pub glob: Option<String>,
+    pub warm_file: Option<String>,
+    pub cache_file: Option<String>,
+    pub prune_older_than: Option<u64>,
+    pub settings_file: Option<String>,
+    pub include_api_key: bool,
+    pub append: bool,
+    pub verbose: bool,
+    pub color: ColorChoice,
+    pub quiet: bool,
+    pub source_lang_detect_threshold: Option<usize>,
 }
 
 #[derive(clap::Parser, Debug)]
@@ -62,11 +138,126 @@ struct Args {
     #[arg(short, long)]
     remove_line_breaks: bool,
 
+    /// Strip leading and trailing whitespace from each line of the input text.
+    #[arg(long)]
+    trim: bool,
+
+    /// Remove blank lines from the input text.
+    #[arg(long)]
+    collapse_blank_lines: bool,
+
+    /// Send the input lines exactly as given, disabling all preprocessing (`--reflow`, `--remove-line-breaks`, `--trim` and `--collapse-blank-lines`).
+    #[arg(long)]
+    plain: bool,
+
+    /// Print only the number of characters of the input and exit, without translating.
+    #[arg(long, conflicts_with_all = ["stream", "input_dir"])]
+    count_only: bool,
+
+    /// Print a summary of the run (segments, cache hits, API calls, characters billed and elapsed time) to stderr.
+    #[arg(long)]
+    summary: bool,
+
+    /// Reflow paragraphs: join the lines in each paragraph (separated by blank lines) and keep the paragraphs.
+    #[arg(long, conflicts_with = "remove_line_breaks")]
+    reflow: bool,
+
+    /// How DeepL API splits the input into sentences (0: no splitting, 1: punctuation and newlines, nonewlines: punctuation only).
+    /// `--reflow` uses `nonewlines` unless specified.
+    #[arg(long, value_parser = ["0", "1", "nonewlines"])]
+    split_sentences: Option<String>,
+
+    /// Keep the translations completed before a failure.
+    /// The successful translations are printed and the untranslated lines are reported.
+    #[arg(long)]
+    keep_going: bool,
+
+    /// Print the lines detected as the target language unchanged instead of their translations.
+    /// Only with the detected source language (without `--from`). The lines are still sent to DeepL API for detection.
+    #[arg(long, conflicts_with_all = ["from", "skip_if_detected"])]
+    skip_same: bool,
+
+    /// Print the lines detected as this language unchanged instead of their translations (see `--skip-same`).
+    #[arg(long, value_name = "LANG", conflicts_with = "from")]
+    skip_if_detected: Option<String>,
+
+    /// Print each source segment above its translation.
+    #[arg(long)]
+    show_source: bool,
+
+    /// Do not use the cache in this run, even if the cache is enabled.
+    #[arg(long, conflicts_with = "cache")]
+    no_cache: bool,
+
+    /// Use the cache in this run, even if the cache is disabled.
+    #[arg(long)]
+    cache: bool,
+
+    /// Abort before sending if the input exceeds this number of characters in this run.
+    /// Overrides the value set by `dptran set --max-chars-per-run`.
+    #[arg(long)]
+    max_chars: Option<usize>,
+
+    /// Warn if the source language is detected automatically and the input is shorter than this number of characters.
+    /// 0 disables the warning.
+    #[arg(long)]
+    source_lang_detect_threshold: Option<usize>,
+
+    /// Glossary ID to use for the translation.
+    /// The source language must be specified by `--from`.
+    #[arg(long)]
+    glossary_id: Option<String>,
+
+    /// Report the glossary applied to the translation to stderr,
+    /// whether it is given by `--glossary-id` or configured for the language pair.
+    #[arg(long)]
+    show_glossary: bool,
+
+    /// Set formality of the translation.
+    /// Only some target languages support formality (see `dptran list -t`).
+    /// `prefer_more` and `prefer_less` fall back to the default formality on unsupported languages.
+    #[arg(long, value_parser = ["default", "more", "less", "prefer_more", "prefer_less"])]
+    formality: Option<String>,
+
+    /// Use `prefer_more` / `prefer_less` instead of `more` / `less` if the target language does not support formality.
+    #[arg(long, requires = "formality")]
+    formality_fallback: bool,
+
+    /// Maximum number of requests sent concurrently when the input is split into multiple requests.
+    #[arg(long)]
+    concurrency: Option<usize>,
+
+    /// Timeout in seconds to establish the connection to DeepL API.
+    #[arg(long, value_name = "SECONDS")]
+    timeout_connect: Option<u64>,
+
+    /// Timeout in seconds of each request to DeepL API, including the connection.
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
+
+    /// Retry a request rejected with 429 Too Many Requests or 503 Service Unavailable up to N times (default: no retry).
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    max_retries: u32,
+
+    /// Delay in milliseconds before the first retry, doubled for each further retry (with `--max-retries`).
+    #[arg(long, value_name = "MS", default_value_t = DEFAULT_RETRY_DELAY_MS)]
+    retry_delay: u64,
+
+    /// Additional parameter of the translation request in `key=value` format, sent to DeepL API as it is.
+    /// Use this to try new DeepL API parameters. Can be specified multiple times.
+    #[arg(long = "param", value_name = "KEY=VALUE", value_parser = parse_param)]
+    params: Vec<(String, String)>,
+
     /// Print usage of DeepL API.
     #[arg(short, long)]
     usage: bool,
 
+    /// Print build information (version, target, rustc version and enabled features).
+    #[arg(long)]
+    build_info: bool,
+
     /// Input file.
+    /// `-` reads from the standard input.
     #[arg(short, long)]
     input_file: Option<String>,
 
@@ -74,11 +265,53 @@ struct Args {
     #[arg(short, long)]
     output_file: Option<String>,
 
+    /// Input directory. The text files in it are translated recursively into `--output-dir`.
+    #[arg(long, requires = "output_dir", conflicts_with_all = ["input_file", "source_text"])]
+    input_dir: Option<String>,
+
+    /// Output directory of `--input-dir`, mirroring the structure of the input directory.
+    #[arg(long, requires = "input_dir")]
+    output_dir: Option<String>,
+
+    /// Translate only the files matching the pattern (e.g. `*.md`) in `--input-dir`.
+    #[arg(long, requires = "input_dir")]
+    glob: Option<String>,
+
+    /// Append to the output file instead of overwriting it.
+    #[arg(long, requires = "output_file")]
+    append: bool,
+
+    /// Stream mode.
+    /// Read the standard input line by line and translate each line as it arrives (e.g. `tail -f log | dptran --stream`).
+    /// `--line-buffered` is an alias.
+    #[arg(long, visible_alias = "line-buffered")]
+    stream: bool,
+
+    /// Show the prompt, translate one input from the standard input and exit.
+    #[arg(long, conflicts_with_all = ["input_file", "stream", "editor", "source_text", "input_dir"])]
+    interactive_once: bool,
+
     /// Editor mode.
     /// The editor can be configured by `dptran set -e <editor_command>`
     #[arg(short, long)]
     editor: bool,
 
+    /// Print debug messages (e.g. cache hits and misses) to stderr.
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// When to color the output: auto (only on a terminal and if `NO_COLOR` is not set), always or never.
+    #[arg(long, value_name = "WHEN", value_parser = ["auto", "always", "never"])]
+    color: Option<String>,
+
+    /// Do not color the output (same as `--color never`).
+    #[arg(long, conflicts_with = "color")]
+    no_color: bool,
+
+    /// Suppress warnings.
+    #[arg(short, long)]
+    quiet: bool,
+
     /// subcommands
     #[clap(subcommand)]
     subcommands: Option<SubCommands>,
@@ -90,7 +323,7 @@ enum SubCommands {
     #[command(group(
         ArgGroup::new("setting_vers")
             .required(true)
-            .args(["api_key", "target_lang", "editor_command", "show", "enable_cache", "disable_cache", "clear"]),
+            .args(["api_key", "target_lang", "reset_target_lang", "editor_command", "legacy_codes", "max_chars_per_run", "formality", "audit_log", "cache_normalize_whitespace", "remember_langs", "glossary_for", "export", "import", "show", "enable_cache", "disable_cache", "clear"]),
     ))]
     Set {
         /// Set api-key.
@@ -101,14 +334,63 @@ enum SubCommands {
         #[arg(short, long)]
         target_lang: Option<String>,
 
+        /// Reset default target language to EN.
+        #[arg(long)]
+        reset_target_lang: bool,
+
         /// Set editor command (e.g. `vi`, `vim` or `emacs -nw`).
         #[arg(short, long)]
         editor_command: Option<String>,
 
+        /// Convert EN and PT to EN-US and PT-PT as before v2.1.0.
+        #[arg(long, value_parser = ["on", "off"])]
+        legacy_codes: Option<String>,
+
+        /// Set max characters translated per run (0: unlimited).
+        #[arg(long)]
+        max_chars_per_run: Option<usize>,
+
+        /// Set default formality, applied to the target languages supporting formality.
+        #[arg(long, value_parser = ["default", "more", "less", "prefer_more", "prefer_less"])]
+        formality: Option<String>,
+
+        /// Set the file to record the metadata of each translation request (never the texts). Empty string to disable.
+        #[arg(long, value_name = "PATH")]
+        audit_log: Option<String>,
+
+        /// Ignore leading, trailing and repeated whitespace when looking up the cache (default: on).
+        #[arg(long, value_parser = ["on", "off"])]
+        cache_normalize_whitespace: Option<String>,
+
+        /// Use the target language of the last translation when `-t` is not specified (default: off).
+        #[arg(long, value_parser = ["on", "off"])]
+        remember_langs: Option<String>,
+
+        /// Set the glossary applied automatically to the language pair (e.g. `--glossary-for EN:JA <GLOSSARY_ID>`). Empty glossary ID to remove.
+        /// Not applied if the source language is detected automatically.
+        #[arg(long, num_args = 2, value_names = ["PAIR", "GLOSSARY_ID"])]
+        glossary_for: Option<Vec<String>>,
+
+        /// Export the settings to a JSON file to use them on another machine. The API key is not included by default.
+        #[arg(long, value_name = "FILE")]
+        export: Option<String>,
+
+        /// Include the API key in the exported settings (with `--export`).
+        #[arg(long, requires = "export")]
+        include_api_key: bool,
+
+        /// Import the settings from a JSON file exported by `--export`. The current API key is kept if the file does not include one.
+        #[arg(long, value_name = "FILE")]
+        import: Option<String>,
+
         /// Show settings.
         #[arg(short, long)]
         show: bool,
 
+        /// Show settings in JSON format (with `--show`).
+        #[arg(long, requires = "show")]
+        json: bool,
+
         /// Enable cache.
         #[arg(long)]
         enable_cache: bool,
@@ -126,7 +408,7 @@ enum SubCommands {
     #[command(group(
         ArgGroup::new("list_vers")
             .required(true)
-            .args(["source_langs", "target_langs"]),
+            .args(["source_langs", "target_langs", "all"]),
     ))]
     List {
         /// List source languages
@@ -136,13 +418,39 @@ enum SubCommands {
         /// List target languages
         #[arg(short, long)]
         target_langs: bool,
+
+        /// List both source and target languages
+        #[arg(short, long)]
+        all: bool,
     },
 
+    /// Check the settings and the connection to DeepL API
+    Doctor,
+
+    /// Check that the API key and DeepL API are working (exits with a non-zero code if not)
+    Ping,
+
+    /// Show version, settings and endpoints for bug reports
+    Info {
+        /// Output as a JSON object
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Generate a shell completion script to stdout
+    Completions {
+        /// Shell to generate the completion script for
+        shell: Shell,
+    },
+
+    /// Generate a man page (roff) to stdout
+    Man,
+
     /// Cache settings
     #[command(group(
         ArgGroup::new("cache_vers")
             .required(true)
-            .args(["max_entries", "clear"]),
+            .args(["max_entries", "clear", "warm", "export", "import", "prune", "list"]),
     ))]
     Cache {
         /// Set cache max entries (default: 100).
@@ -152,9 +460,74 @@ enum SubCommands {
         /// Clear chache.
         #[arg(short, long)]
         clear: bool,
+
+        /// Translate the phrases in the file (one per line) and store them in the cache.
+        /// The languages are specified with `--to` / `--from` (e.g. `dptran cache --warm phrases.txt --to JA`).
+        #[arg(short, long, value_name = "FILE")]
+        warm: Option<String>,
+
+        /// Export the cache entries to a JSON file to share them.
+        #[arg(long, value_name = "FILE")]
+        export: Option<String>,
+
+        /// Import the cache entries from a JSON file exported by `--export`, keeping the existing entries.
+        #[arg(long, value_name = "FILE")]
+        import: Option<String>,
+
+        /// Remove the cache entries translated into the target language (and from the source language if specified) and/or older than `--older-than`.
+        /// The languages are specified with `--to` / `--from` (e.g. `dptran cache --prune --to JA --from EN`).
+        #[arg(long)]
+        prune: bool,
+
+        /// With `--prune`, remove only the entries stored more than this number of days ago.
+        #[arg(long, value_name = "DAYS", requires = "prune")]
+        older_than: Option<u64>,
+
+        /// List the cache entries (hash, languages and translation).
+        #[arg(long)]
+        list: bool,
+
+        /// Source language of `--warm` and `--prune`. Also accepted before the subcommand (`dptran -f EN cache ...`).
+        #[arg(short, long)]
+        from: Option<String>,
+
+        /// Target language of `--warm` and `--prune`. Also accepted before the subcommand (`dptran -t JA cache ...`).
+        #[arg(short, long, visible_alias = "target")]
+        to: Option<String>,
     },
 }
 
+/// Convert the value of `--formality` to Formality.
+fn parse_formality(formality: &str) -> Formality {
+    match formality {
+        "more" => Formality::More,
+        "less" => Formality::Less,
+        "prefer_more" => Formality::PreferMore,
+        "prefer_less" => Formality::PreferLess,
+        _ => Formality::Default,
+    }
+}
+
+/// Parse ``key=value`` of ``--param``.
+fn parse_param(param: &str) -> Result<(String, String), String> {
+    match param.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("invalid parameter \"{}\": expected key=value", param)),
+    }
+}
+
+/// Write the completion script for the shell, derived from the command line definitions.
+pub fn generate_completions(shell: Shell, buf: &mut impl Write) {
+    let mut command = Args::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, name, buf);
+}
+
+/// Write the man page rendered from the command line definitions.
+pub fn generate_man_page(buf: &mut impl Write) -> io::Result<()> {
+    clap_mangen::Man::new(Args::command()).render(buf)
+}
+
 fn load_stdin() -> io::Result<Option<String>> {
     if atty::is(Stream::Stdin) {
         return Ok(None);
@@ -164,9 +537,21 @@ fn load_stdin() -> io::Result<Option<String>> {
     Ok(Some(buffer))
 }
 
+/// Read the input file.
+/// "-" means the standard input (given as stdin), which is read even if it is a terminal.
+fn read_input_file(filepath: &str, stdin: &mut impl Read) -> io::Result<String> {
+    if filepath == "-" {
+        let mut buffer = String::new();
+        stdin.read_to_string(&mut buffer)?;
+        Ok(buffer)
+    } else {
+        std::fs::read_to_string(filepath)
+    }
+}
+
 fn read_from_editor() -> Result<String, RuntimeError> {
     // Get editor command
-    let editor = configure::get_editor_command().map_err(|e| RuntimeError::ConfigError(e))?;
+    let editor = configure::get_editor_command()?;
     if let Some(editor) = editor {
         // Parse the editor command and the arguments
         // e.g., "emacs -nw" -> "emacs", "-nw"
@@ -174,7 +559,7 @@ fn read_from_editor() -> Result<String, RuntimeError> {
         let editor = editor_args.next().unwrap();
         let editor_args = editor_args.collect::<Vec<&str>>().join(" ");
         // Get tmp file path
-        let config_filepath = configure::get_config_file_path().map_err(|e| RuntimeError::ConfigError(e))?;
+        let config_filepath = configure::get_config_file_path()?;
         let tmp_filepath = config_filepath.parent().unwrap().join("tmp.txt");
         // Open by the editor
         let mut child = if editor_args.len() > 0 {
@@ -211,12 +596,58 @@ pub fn parser() -> Result<ArgStruct, RuntimeError> {
         default_target_lang: None,
         cache_max_entries: None,
         editor_command: None,
+        legacy_lang_expansion: None,
+        max_chars_per_run: None,
+        default_formality: None,
+        audit_log_path: None,
+        cache_normalize_whitespace: None,
+        remember_last_langs: None,
+        glossary_for: None,
+        completion_shell: None,
+        json: false,
         translate_from: None,
         translate_to: None,
         multilines: false,
         remove_line_breaks: false,
+        reflow: false,
+        trim: false,
+        collapse_blank_lines: false,
+        plain: false,
+        count_only: false,
+        summary: false,
+        interactive_once: false,
+        split_sentences: None,
+        keep_going: false,
+        skip_same: false,
+        skip_if_detected: None,
+        show_source: false,
+        show_glossary: false,
+        cache_override: None,
+        max_chars: None,
+        glossary_id: None,
+        formality: None,
+        formality_fallback: false,
+        concurrency: None,
+        connect_timeout: None,
+        request_timeout: None,
+        max_retries: 0,
+        retry_delay: DEFAULT_RETRY_DELAY_MS,
+        extra_params: Vec::new(),
         source_text: None,
         ofile_path: None,
+        input_dir: None,
+        output_dir: None,
+        glob: None,
+        warm_file: None,
+        cache_file: None,
+        prune_older_than: None,
+        settings_file: None,
+        include_api_key: false,
+        append: false,
+        verbose: false,
+        color: ColorChoice::Auto,
+        quiet: false,
+        source_lang_detect_threshold: None,
     };
 
     // Multilines
@@ -224,26 +655,116 @@ pub fn parser() -> Result<ArgStruct, RuntimeError> {
         arg_struct.multilines = true;
     }
 
+    // Verbose
+    if args.verbose {
+        arg_struct.verbose = true;
+    }
+
+    // Color output
+    arg_struct.color = match args.color.as_deref() {
+        Some("always") => ColorChoice::Always,
+        Some("never") => ColorChoice::Never,
+        _ if args.no_color => ColorChoice::Never,
+        _ => ColorChoice::Auto,
+    };
+
+    // Quiet
+    if args.quiet {
+        arg_struct.quiet = true;
+    }
+
+    // Threshold of the warning for short inputs
+    if let Some(threshold) = args.source_lang_detect_threshold {
+        arg_struct.source_lang_detect_threshold = Some(threshold);
+    }
+
     // Remove line breaks
     if args.remove_line_breaks == true {
         arg_struct.remove_line_breaks = true;
     }
 
+    // Clean up the input
+    if args.trim {
+        arg_struct.trim = true;
+    }
+    if args.collapse_blank_lines {
+        arg_struct.collapse_blank_lines = true;
+    }
+    if args.plain {
+        arg_struct.plain = true;
+    }
+    if args.count_only {
+        arg_struct.count_only = true;
+    }
+    if args.summary {
+        arg_struct.summary = true;
+    }
+
+    // Reflow paragraphs
+    if args.reflow {
+        arg_struct.reflow = true;
+    }
+    if let Some(split_sentences) = args.split_sentences {
+        arg_struct.split_sentences = Some(match split_sentences.as_str() {
+            "0" => SplitSentences::Off,
+            "nonewlines" => SplitSentences::NoNewlines,
+            _ => SplitSentences::On,
+        });
+    }
+
+    // Keep going
+    if args.keep_going {
+        arg_struct.keep_going = true;
+    }
+
+    // Print the lines in the detected language unchanged
+    if args.skip_same {
+        arg_struct.skip_same = true;
+    }
+    arg_struct.skip_if_detected = args.skip_if_detected;
+
+    // Show source
+    if args.show_source {
+        arg_struct.show_source = true;
+    }
+
+    // Show glossary
+    if args.show_glossary {
+        arg_struct.show_glossary = true;
+    }
+
+    // Override the cache setting in this run
+    if args.no_cache {
+        arg_struct.cache_override = Some(false);
+    }
+    else if args.cache {
+        arg_struct.cache_override = Some(true);
+    }
+
     // Usage
     if args.usage == true {
         arg_struct.execution_mode = ExecutionMode::PrintUsage;
         return Ok(arg_struct);
     }
 
+    // Build information
+    if args.build_info {
+        arg_struct.execution_mode = ExecutionMode::PrintBuildInfo;
+        return Ok(arg_struct);
+    }
+
     // Output file
     if let Some(ofile_path) = args.output_file {
         arg_struct.ofile_path = Some(ofile_path);
     }
+    if args.append {
+        arg_struct.append = true;
+    }
 
     // Subcommands
     if let Some(subcommands) = args.subcommands {
         match subcommands {
-            SubCommands::Set { api_key, target_lang: default_lang,  editor_command, show, enable_cache, disable_cache, clear } => {
+            SubCommands::Set { api_key, target_lang: default_lang, reset_target_lang, editor_command, legacy_codes, max_chars_per_run, formality, audit_log, cache_normalize_whitespace, remember_langs, glossary_for, export, include_api_key, import, show, json, enable_cache, disable_cache, clear } => {
                 if let Some(api_key) = api_key {
                     arg_struct.execution_mode = ExecutionMode::SetApiKey;
                     arg_struct.api_key = Some(api_key);
@@ -252,12 +773,53 @@ pub fn parser() -> Result<ArgStruct, RuntimeError> {
                     arg_struct.execution_mode = ExecutionMode::SetDefaultTargetLang;
                     arg_struct.default_target_lang = Some(default_lang);
                 }
+                if reset_target_lang {
+                    arg_struct.execution_mode = ExecutionMode::ResetDefaultTargetLang;
+                }
                 if let Some(editor_command) = editor_command {
                     arg_struct.execution_mode = ExecutionMode::SetEditor;
                     arg_struct.editor_command = Some(editor_command);
                 }
+                if let Some(legacy_codes) = legacy_codes {
+                    arg_struct.execution_mode = ExecutionMode::SetLegacyLangExpansion;
+                    arg_struct.legacy_lang_expansion = Some(legacy_codes == "on");
+                }
+                if let Some(max_chars_per_run) = max_chars_per_run {
+                    arg_struct.execution_mode = ExecutionMode::SetMaxCharsPerRun;
+                    arg_struct.max_chars_per_run = Some(max_chars_per_run);
+                }
+                if let Some(formality) = formality {
+                    arg_struct.execution_mode = ExecutionMode::SetDefaultFormality;
+                    arg_struct.default_formality = Some(parse_formality(&formality));
+                }
+                if let Some(audit_log) = audit_log {
+                    arg_struct.execution_mode = ExecutionMode::SetAuditLogPath;
+                    arg_struct.audit_log_path = Some(audit_log);
+                }
+                if let Some(cache_normalize_whitespace) = cache_normalize_whitespace {
+                    arg_struct.execution_mode = ExecutionMode::SetCacheNormalizeWhitespace;
+                    arg_struct.cache_normalize_whitespace = Some(cache_normalize_whitespace == "on");
+                }
+                if let Some(glossary_for) = glossary_for {
+                    arg_struct.execution_mode = ExecutionMode::SetGlossaryFor;
+                    arg_struct.glossary_for = Some((glossary_for[0].clone(), glossary_for[1].clone()));
+                }
+                if let Some(remember_langs) = remember_langs {
+                    arg_struct.execution_mode = ExecutionMode::SetRememberLastLangs;
+                    arg_struct.remember_last_langs = Some(remember_langs == "on");
+                }
+                if let Some(export) = export {
+                    arg_struct.execution_mode = ExecutionMode::ExportSettings;
+                    arg_struct.settings_file = Some(export);
+                    arg_struct.include_api_key = include_api_key;
+                }
+                if let Some(import) = import {
+                    arg_struct.execution_mode = ExecutionMode::ImportSettings;
+                    arg_struct.settings_file = Some(import);
+                }
                 if show == true {
                     arg_struct.execution_mode = ExecutionMode::DisplaySettings;
+                    arg_struct.json = json;
                 }
                 if enable_cache == true {
                     arg_struct.execution_mode = ExecutionMode::EnableCache;
@@ -270,16 +832,41 @@ pub fn parser() -> Result<ArgStruct, RuntimeError> {
                 }
                 return Ok(arg_struct);
             }
-            SubCommands::List { source_langs, target_langs } => {
+            SubCommands::List { source_langs, target_langs, all } => {
                 if source_langs == true {
                     arg_struct.execution_mode = ExecutionMode::ListSourceLangs;
                 }
                 if target_langs == true {
                     arg_struct.execution_mode = ExecutionMode::ListTargetLangs;
                 }
+                if all {
+                    arg_struct.execution_mode = ExecutionMode::ListAllLangs;
+                }
+                return Ok(arg_struct);
+            }
+            SubCommands::Doctor => {
+                arg_struct.execution_mode = ExecutionMode::Doctor;
+                return Ok(arg_struct);
+            }
+            SubCommands::Ping => {
+                arg_struct.execution_mode = ExecutionMode::Ping;
+                return Ok(arg_struct);
+            }
+            SubCommands::Info { json } => {
+                arg_struct.execution_mode = ExecutionMode::Info;
+                arg_struct.json = json;
                 return Ok(arg_struct);
             }
-            SubCommands::Cache { max_entries, clear } => {
+            SubCommands::Completions { shell } => {
+                arg_struct.execution_mode = ExecutionMode::GenerateCompletions;
+                arg_struct.completion_shell = Some(shell);
+                return Ok(arg_struct);
+            }
+            SubCommands::Man => {
+                arg_struct.execution_mode = ExecutionMode::GenerateManPage;
+                return Ok(arg_struct);
+            }
+            SubCommands::Cache { max_entries, clear, warm, export, import, prune, older_than, list, from, to } => {
                 if let Some(max_entries) = max_entries {
                     arg_struct.execution_mode = ExecutionMode::SetCacheMaxEntries;
                     arg_struct.cache_max_entries = Some(max_entries);
@@ -287,6 +874,28 @@ pub fn parser() -> Result<ArgStruct, RuntimeError> {
                 if clear == true {
                     arg_struct.execution_mode = ExecutionMode::ClearCache;
                 }
+                if let Some(warm) = warm {
+                    arg_struct.execution_mode = ExecutionMode::WarmCache;
+                    arg_struct.warm_file = Some(warm);
+                }
+                if let Some(export) = export {
+                    arg_struct.execution_mode = ExecutionMode::ExportCache;
+                    arg_struct.cache_file = Some(export);
+                }
+                if let Some(import) = import {
+                    arg_struct.execution_mode = ExecutionMode::ImportCache;
+                    arg_struct.cache_file = Some(import);
+                }
+                if prune {
+                    arg_struct.execution_mode = ExecutionMode::PruneCache;
+                    arg_struct.prune_older_than = older_than;
+                }
+                if list {
+                    arg_struct.execution_mode = ExecutionMode::ListCache;
+                }
+                // The languages of --warm and --prune (after or before the subcommand)
+                arg_struct.translate_from = from.or(args.from);
+                arg_struct.translate_to = to.or(args.to);
                 return Ok(arg_struct);
             }
         }
@@ -299,10 +908,46 @@ pub fn parser() -> Result<ArgStruct, RuntimeError> {
     if let Some(to) = args.to {
         arg_struct.translate_to = Some(to);
     }
+    if let Some(max_chars) = args.max_chars {
+        arg_struct.max_chars = Some(max_chars);
+    }
+    if let Some(glossary_id) = args.glossary_id {
+        arg_struct.glossary_id = Some(glossary_id);
+    }
+    if let Some(formality) = args.formality {
+        arg_struct.formality = Some(parse_formality(&formality));
+    }
+    if args.formality_fallback {
+        arg_struct.formality_fallback = true;
+    }
+    if let Some(concurrency) = args.concurrency {
+        arg_struct.concurrency = Some(concurrency);
+    }
+    arg_struct.extra_params = args.params;
+    arg_struct.connect_timeout = args.timeout_connect;
+    arg_struct.request_timeout = args.timeout;
+    arg_struct.max_retries = args.max_retries;
+    arg_struct.retry_delay = args.retry_delay;
+    // If input directory is specified, translate the files in it
+    if let Some(input_dir) = args.input_dir {
+        arg_struct.execution_mode = ExecutionMode::TranslateDirectory;
+        arg_struct.input_dir = Some(input_dir);
+        arg_struct.output_dir = args.output_dir;
+        arg_struct.glob = args.glob;
+    }
     // If input file is specified, read from the file
-    if let Some(filepath) = args.input_file {
+    else if let Some(filepath) = args.input_file {
         arg_struct.execution_mode = ExecutionMode::TranslateNormal;
-        arg_struct.source_text = Some(std::fs::read_to_string(&filepath).map_err(|e| RuntimeError::FileIoError(e.to_string()))?);
+        arg_struct.source_text = Some(read_input_file(&filepath, &mut io::stdin()).map_err(|e| RuntimeError::FileIoError(e.to_string()))?);
+    }
+    // If stream mode is specified, read from stdin line by line later
+    else if args.stream {
+        arg_struct.execution_mode = ExecutionMode::TranslateStream;
+    }
+    // If interactive-once mode is specified, read one input from stdin later (even if it is piped)
+    else if args.interactive_once {
+        arg_struct.execution_mode = ExecutionMode::TranslateInteractive;
+        arg_struct.interactive_once = true;
     }
     // If editor mode is specified, read from stdin
     else if args.editor == true {
@@ -328,5 +973,229 @@ pub fn parser() -> Result<ArgStruct, RuntimeError> {
             },
         };
     }
+    // Normalize the line endings so that the translations and the cache keys do not depend on the platform
+    arg_struct.source_text = arg_struct.source_text.map(|text| normalize_line_endings(&text));
     Ok(arg_struct)
 }
+
+/// Convert CRLF and CR line endings to LF.
+pub fn normalize_line_endings(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+#[test]
+fn glossary_id_parse_test() {
+    let args = Args::try_parse_from(["dptran", "-f", "EN", "-t", "JA", "--glossary-id", "def3a26b-3e84-45b3-84ae-0c0aaf3525f7", "Hello"]).unwrap();
+    assert_eq!(args.glossary_id, Some("def3a26b-3e84-45b3-84ae-0c0aaf3525f7".to_string()));
+    // --glossary-id requires a value
+    assert!(Args::try_parse_from(["dptran", "--glossary-id"]).is_err());
+    // --show-glossary also reports the glossary configured for the language pair
+    let args = Args::try_parse_from(["dptran", "-f", "EN", "-t", "JA", "--show-glossary", "Hello"]).unwrap();
+    assert!(args.show_glossary && args.glossary_id.is_none());
+}
+
+#[test]
+fn cache_override_parse_test() {
+    let args = Args::try_parse_from(["dptran", "--no-cache", "Hello"]).unwrap();
+    assert!(args.no_cache && !args.cache);
+    let args = Args::try_parse_from(["dptran", "--cache", "Hello"]).unwrap();
+    assert!(args.cache && !args.no_cache);
+    // --no-cache and --cache cannot be used together
+    assert!(Args::try_parse_from(["dptran", "--no-cache", "--cache", "Hello"]).is_err());
+}
+
+#[test]
+fn generate_completions_test() {
+    let mut buf = Vec::new();
+    generate_completions(Shell::Bash, &mut buf);
+    let script = String::from_utf8(buf).unwrap();
+    assert!(script.contains("dptran"));
+    assert!(script.contains("cache"));
+    assert!(script.contains("list"));
+    assert!(script.contains("--glossary-id"));
+}
+
+#[test]
+fn generate_man_page_test() {
+    let mut buf = Vec::new();
+    generate_man_page(&mut buf).unwrap();
+    let page = String::from_utf8(buf).unwrap();
+    assert!(page.contains(".TH dptran"));
+    assert!(page.contains("dptran"));
+}
+
+#[test]
+fn list_all_parse_test() {
+    let args = Args::try_parse_from(["dptran", "list", "--all"]).unwrap();
+    match args.subcommands {
+        Some(SubCommands::List { source_langs, target_langs, all }) => assert!(all && !source_langs && !target_langs),
+        _ => panic!("Error: list subcommand is not parsed"),
+    }
+    // One of -s, -t and -a is required
+    assert!(Args::try_parse_from(["dptran", "list"]).is_err());
+    assert!(Args::try_parse_from(["dptran", "list", "-s", "-a"]).is_err());
+}
+
+#[test]
+fn read_input_file_test() {
+    // "-" reads from the standard input
+    let mut stdin = io::Cursor::new("Hello, world!\n".as_bytes());
+    assert_eq!(read_input_file("-", &mut stdin).unwrap(), "Hello, world!\n");
+    // Otherwise, the file is read and the standard input is not touched
+    let mut stdin = io::Cursor::new("Hello, world!\n".as_bytes());
+    assert!(read_input_file("no_such_file_for_read_input_file_test.txt", &mut stdin).is_err());
+    assert_eq!(stdin.position(), 0);
+}
+
+#[test]
+fn param_parse_test() {
+    assert_eq!(parse_param("model_type=quality_optimized"), Ok(("model_type".to_string(), "quality_optimized".to_string())));
+    assert_eq!(parse_param("context=a=b"), Ok(("context".to_string(), "a=b".to_string())));
+    assert!(parse_param("model_type").is_err());
+    assert!(parse_param("=value").is_err());
+
+    let args = Args::try_parse_from(["dptran", "--param", "model_type=latency_optimized", "--param", "show_billed_characters=1", "Hello"]).unwrap();
+    assert_eq!(args.params, vec![("model_type".to_string(), "latency_optimized".to_string()), ("show_billed_characters".to_string(), "1".to_string())]);
+}
+
+#[test]
+fn show_settings_json_parse_test() {
+    let args = Args::try_parse_from(["dptran", "set", "--show", "--json"]).unwrap();
+    match args.subcommands {
+        Some(SubCommands::Set { show, json, .. }) => assert!(show && json),
+        _ => panic!("Error: not parsed as set"),
+    }
+    // --json requires --show
+    assert!(Args::try_parse_from(["dptran", "set", "--json"]).is_err());
+}
+
+#[test]
+fn normalize_line_endings_test() {
+    assert_eq!(normalize_line_endings("Hello\r\nworld\r\n"), "Hello\nworld\n");
+    assert_eq!(normalize_line_endings("Hello\rworld"), "Hello\nworld");
+    assert_eq!(normalize_line_endings("Hello\nworld"), "Hello\nworld");
+    // CRLF read from the standard input
+    let mut stdin = io::Cursor::new("Hello\r\n\r\nworld\r\n".as_bytes());
+    let text = normalize_line_endings(&read_input_file("-", &mut stdin).unwrap());
+    assert!(!text.contains('\r'));
+}
+
+#[test]
+fn input_dir_parse_test() {
+    let args = Args::try_parse_from(["dptran", "--input-dir", "docs", "--output-dir", "docs_ja", "--glob", "*.md", "-t", "JA"]).unwrap();
+    assert_eq!(args.input_dir, Some("docs".to_string()));
+    assert_eq!(args.output_dir, Some("docs_ja".to_string()));
+    assert_eq!(args.glob, Some("*.md".to_string()));
+    // --input-dir requires --output-dir
+    assert!(Args::try_parse_from(["dptran", "--input-dir", "docs"]).is_err());
+}
+
+#[test]
+fn cache_warm_parse_test() {
+    let args = Args::try_parse_from(["dptran", "-t", "JA", "cache", "--warm", "phrases.txt"]).unwrap();
+    assert_eq!(args.to, Some("JA".to_string()));
+    match args.subcommands {
+        Some(SubCommands::Cache { warm, .. }) => assert_eq!(warm, Some("phrases.txt".to_string())),
+        _ => panic!("Error: not parsed as cache"),
+    }
+}
+
+#[test]
+fn cache_prune_parse_test() {
+    let args = Args::try_parse_from(["dptran", "-t", "JA", "-f", "EN", "cache", "--prune"]).unwrap();
+    assert_eq!(args.to, Some("JA".to_string()));
+    assert_eq!(args.from, Some("EN".to_string()));
+    match args.subcommands {
+        Some(SubCommands::Cache { prune, .. }) => assert!(prune),
+        _ => panic!("Error: not parsed as cache"),
+    }
+    let args = Args::try_parse_from(["dptran", "cache", "--prune", "--older-than", "30"]).unwrap();
+    match args.subcommands {
+        Some(SubCommands::Cache { older_than, .. }) => assert_eq!(older_than, Some(30)),
+        _ => panic!("Error: not parsed as cache"),
+    }
+    assert!(Args::try_parse_from(["dptran", "cache", "--older-than", "30"]).is_err());
+
+    // The languages after the subcommand
+    let args = Args::try_parse_from(["dptran", "cache", "--prune", "--to", "JA", "--from", "EN"]).unwrap();
+    match args.subcommands {
+        Some(SubCommands::Cache { prune, to, from, .. }) => {
+            assert!(prune);
+            assert_eq!(to, Some("JA".to_string()));
+            assert_eq!(from, Some("EN".to_string()));
+        }
+        _ => panic!("Error: not parsed as cache"),
+    }
+    assert!(Args::try_parse_from(["dptran", "cache", "--warm", "phrases.txt", "-t", "JA"]).is_ok());
+    match Args::try_parse_from(["dptran", "cache", "--prune", "--target", "JA"]).unwrap().subcommands {
+        Some(SubCommands::Cache { to, .. }) => assert_eq!(to, Some("JA".to_string())),
+        _ => panic!("Error: not parsed as cache"),
+    }
+}
+
+#[test]
+fn color_parse_test() {
+    let args = Args::try_parse_from(["dptran", "--no-color", "Hello"]).unwrap();
+    assert!(args.no_color);
+    let args = Args::try_parse_from(["dptran", "--color", "always", "Hello"]).unwrap();
+    assert_eq!(args.color.as_deref(), Some("always"));
+    assert!(Args::try_parse_from(["dptran", "--color", "always", "--no-color", "Hello"]).is_err());
+}
+
+#[test]
+fn line_buffered_parse_test() {
+    let args = Args::try_parse_from(["dptran", "--line-buffered", "-t", "en"]).unwrap();
+    assert!(args.stream);
+}
+
+#[test]
+fn interactive_once_parse_test() {
+    let args = Args::try_parse_from(["dptran", "--interactive-once", "-t", "ja"]).unwrap();
+    assert!(args.interactive_once);
+    assert!(Args::try_parse_from(["dptran", "--interactive-once", "Hello"]).is_err());
+}
+
+#[test]
+fn glossary_for_parse_test() {
+    let args = Args::try_parse_from(["dptran", "set", "--glossary-for", "EN:JA", "def3a26b-3e84-45b3-84ae-0c0aaf3525f7"]).unwrap();
+    match args.subcommands {
+        Some(SubCommands::Set { glossary_for, .. }) => assert_eq!(glossary_for, Some(vec!["EN:JA".to_string(), "def3a26b-3e84-45b3-84ae-0c0aaf3525f7".to_string()])),
+        _ => panic!("Error: not parsed as set"),
+    }
+    assert!(Args::try_parse_from(["dptran", "set", "--glossary-for", "EN:JA"]).is_err());
+}
+
+#[test]
+fn skip_same_parse_test() {
+    let args = Args::try_parse_from(["dptran", "--skip-same", "-t", "EN", "Hello"]).unwrap();
+    assert!(args.skip_same);
+    // The source language must be detected
+    assert!(Args::try_parse_from(["dptran", "--skip-same", "-f", "EN", "-t", "EN", "Hello"]).is_err());
+    let args = Args::try_parse_from(["dptran", "--skip-if-detected", "JA", "Hello"]).unwrap();
+    assert_eq!(args.skip_if_detected, Some("JA".to_string()));
+}
+
+#[test]
+fn settings_export_import_parse_test() {
+    let args = Args::try_parse_from(["dptran", "set", "--export", "settings.json", "--include-api-key"]).unwrap();
+    match args.subcommands {
+        Some(SubCommands::Set { export, include_api_key, .. }) => {
+            assert_eq!(export, Some("settings.json".to_string()));
+            assert!(include_api_key);
+        }
+        _ => panic!("Error: not parsed as set"),
+    }
+    assert!(Args::try_parse_from(["dptran", "set", "--import", "settings.json"]).is_ok());
+    assert!(Args::try_parse_from(["dptran", "set", "--export", "a.json", "--import", "b.json"]).is_err());
+}
+
+#[test]
+fn retry_parse_test() {
+    let args = Args::try_parse_from(["dptran", "--max-retries", "3", "--retry-delay", "500", "Hello"]).unwrap();
+    assert_eq!(args.max_retries, 3);
+    assert_eq!(args.retry_delay, 500);
+    // No retry by default
+    let args = Args::try_parse_from(["dptran", "Hello"]).unwrap();
+    assert_eq!(args.max_retries, 0);
+    assert_eq!(args.retry_delay, DEFAULT_RETRY_DELAY_MS);
+}
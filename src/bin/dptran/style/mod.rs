@@ -0,0 +1,91 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+use atty::Stream;
+
+/// When to color the output.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColorChoice {
+    /// Color only if the output is a terminal and `NO_COLOR` is not set
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    fn from_u8(value: u8) -> ColorChoice {
+        match value {
+            1 => ColorChoice::Always,
+            2 => ColorChoice::Never,
+            _ => ColorChoice::Auto,
+        }
+    }
+}
+
+/// Color choice of this run (`ColorChoice` as u8).
+static COLOR_CHOICE: AtomicU8 = AtomicU8::new(ColorChoice::Auto as u8);
+
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const RESET: &str = "\x1b[0m";
+
+/// Set the color choice of this run.
+pub fn init(color_choice: ColorChoice) {
+    COLOR_CHOICE.store(color_choice as u8, Ordering::Relaxed);
+}
+
+/// Whether to color the output.
+/// `NO_COLOR` with any non-empty value disables the color in auto mode (https://no-color.org/).
+fn color_enabled(color_choice: ColorChoice, is_terminal: bool, no_color: bool) -> bool {
+    match color_choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => is_terminal && !no_color,
+    }
+}
+
+/// Whether to color the output to the stream.
+fn enabled(stream: Stream) -> bool {
+    let no_color = std::env::var_os("NO_COLOR").map(|v| !v.is_empty()).unwrap_or(false);
+    color_enabled(ColorChoice::from_u8(COLOR_CHOICE.load(Ordering::Relaxed)), atty::is(stream), no_color)
+}
+
+/// Wrap the text in the escape sequence if enabled.
+fn paint(text: &str, code: &str, enabled: bool) -> String {
+    if enabled {
+        format!("{}{}{}", code, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Highlight the translated text printed to stdout.
+pub fn translated(text: &str) -> String {
+    paint(text, BOLD, enabled(Stream::Stdout))
+}
+
+/// Dim the prompt printed to stdout.
+pub fn prompt(text: &str) -> String {
+    paint(text, DIM, enabled(Stream::Stdout))
+}
+
+/// Color the error message printed to stderr.
+pub fn error(text: &str) -> String {
+    paint(text, RED, enabled(Stream::Stderr))
+}
+
+#[test]
+fn color_enabled_test() {
+    // Piped output is not colored unless forced
+    assert!(!color_enabled(ColorChoice::Auto, false, false));
+    assert!(color_enabled(ColorChoice::Always, false, false));
+    // Terminal output is colored unless disabled
+    assert!(color_enabled(ColorChoice::Auto, true, false));
+    assert!(!color_enabled(ColorChoice::Auto, true, true));
+    assert!(!color_enabled(ColorChoice::Never, true, false));
+
+    // No ANSI codes when disabled
+    let text = paint("Hallo, Welt!", BOLD, color_enabled(ColorChoice::Auto, false, false));
+    assert_eq!(text, "Hallo, Welt!");
+    assert!(!text.contains('\x1b'));
+    assert_eq!(paint("Hallo", RED, true), "\x1b[31mHallo\x1b[0m");
+}
@@ -1,13 +1,30 @@
 use std::io;
 use std::fmt;
+use std::sync::RwLock;
 use serde_json::Value;
+use serde::{Deserialize, Serialize};
 
 mod connection;
 pub use connection::ConnectionError;
+use connection::ConditionalResponse;
+pub use connection::{set_accept_invalid_certs, set_connect_timeout, set_request_timeout, set_retry_policy};
+#[cfg(test)]
+pub(crate) use connection::TEST_LOCK;
 
-const DEEPL_API_TRANSLATE: &str = "https://api-free.deepl.com/v2/translate";
-const DEEPL_API_USAGE: &str = "https://api-free.deepl.com/v2/usage";
-const DEEPL_API_LANGUAGES: &str = "https://api-free.deepl.com/v2/languages";
+pub const DEEPL_API_TRANSLATE: &str = "https://api-free.deepl.com/v2/translate";
+pub const DEEPL_API_USAGE: &str = "https://api-free.deepl.com/v2/usage";
+pub const DEEPL_API_LANGUAGES: &str = "https://api-free.deepl.com/v2/languages";
+
+/// Endpoints overriding the defaults above (None: default). See ``set_translate_endpoint()`` and so on.
+static TRANSLATE_ENDPOINT: RwLock<Option<String>> = RwLock::new(None);
+static USAGE_ENDPOINT: RwLock<Option<String>> = RwLock::new(None);
+static LANGUAGES_ENDPOINT: RwLock<Option<String>> = RwLock::new(None);
+
+/// Maximum number of texts that can be sent in a single translation request.
+const DEEPL_API_TRANSLATE_MAX_TEXTS: usize = 50;
+
+/// Maximum total size (bytes) of a single translation request.
+pub const DEEPL_API_REQUEST_MAX_BYTES: usize = 128 * 1024;
 
 /// Language code and language name
 pub type LangCodeName = (String, String);
@@ -23,16 +40,20 @@ enum LangType {
 /// although only text translation is supported. Additionally, if the language code is unspecified variant, it is not returned.
 /// Therefore, dptran adds the following language codes and names manually.
 /// This constants must be updated when the DeepL API is updated.
+/// The last element is whether the language supports the formality parameter.
 /// See <https://https://developers.deepl.com/docs/resources/supported-languages>.
 
-static EXTENDED_LANG_CODES: [(&str, &str, LangType); 5] = [
-    ("AR", "Arabic", LangType::Source),
-    ("AR", "Arabic", LangType::Target),
-    ("EN", "English", LangType::Target),
-    ("PT", "Portuguese", LangType::Target),
-    ("ZH-HANT", "Chinese (traditional)", LangType::Target)
+static EXTENDED_LANG_CODES: [(&str, &str, LangType, bool); 5] = [
+    ("AR", "Arabic", LangType::Source, false),
+    ("AR", "Arabic", LangType::Target, false),
+    ("EN", "English", LangType::Target, false),
+    ("PT", "Portuguese", LangType::Target, true),
+    ("ZH-HANT", "Chinese (traditional)", LangType::Target, false)
 ];
 
+/// Maximum number of characters of the ``JsonError`` content shown by ``Display``.
+pub const JSON_ERROR_DISPLAY_MAX_CHARS: usize = 500;
+
 /// DeepL API error.  
 /// ``ConnectionError``: Connection error occurred in the process of sending and receiving data.  
 /// ``JsonError``: Error occurred while parsing json.  
@@ -49,42 +70,262 @@ impl fmt::Display for DeeplAPIError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             DeeplAPIError::ConnectionError(ref e) => write!(f, "Connection error: {}", e),
-            DeeplAPIError::JsonError(ref e) => write!(f, "JSON error: {}", e),
+            DeeplAPIError::JsonError(ref e) => {
+                // The content may be long, so it is truncated
+                if e.chars().count() > JSON_ERROR_DISPLAY_MAX_CHARS {
+                    let truncated = e.chars().take(JSON_ERROR_DISPLAY_MAX_CHARS).collect::<String>();
+                    write!(f, "JSON error: {}…(truncated)", truncated)
+                } else {
+                    write!(f, "JSON error: {}", e)
+                }
+            },
             DeeplAPIError::LimitError => write!(f, "The translation limit of your account has been reached. Consider upgrading your subscription."),
             DeeplAPIError::GetLanguageCodesError => write!(f, "Could not get language codes"),
         }
     }
 }
 
+/// Formality of the translation.  
+/// Only some target languages support the formality parameter.  
+/// ``Default``: Default formality  
+/// ``More``: More formal language  
+/// ``Less``: More informal language  
+/// ``PreferMore``: More formal language if available, otherwise default  
+/// ``PreferLess``: More informal language if available, otherwise default  
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Formality {
+    Default,
+    More,
+    Less,
+    PreferMore,
+    PreferLess,
+}
+impl Formality {
+    /// Get the ``prefer_`` variant, which falls back to the default formality on unsupported languages.
+    pub fn to_prefer(self) -> Formality {
+        match self {
+            Formality::More => Formality::PreferMore,
+            Formality::Less => Formality::PreferLess,
+            f => f,
+        }
+    }
+}
+impl fmt::Display for Formality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Formality::Default => write!(f, "default"),
+            Formality::More => write!(f, "more"),
+            Formality::Less => write!(f, "less"),
+            Formality::PreferMore => write!(f, "prefer_more"),
+            Formality::PreferLess => write!(f, "prefer_less"),
+        }
+    }
+}
+
+/// How DeepL API splits the input into sentences.  
+/// ``Off``: No splitting at all (``0``)  
+/// ``On``: Split on punctuation and on newlines (``1``, default of DeepL API)  
+/// ``NoNewlines``: Split on punctuation only, ignoring newlines (``nonewlines``)  
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitSentences {
+    Off,
+    On,
+    NoNewlines,
+}
+impl fmt::Display for SplitSentences {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SplitSentences::Off => write!(f, "0"),
+            SplitSentences::On => write!(f, "1"),
+            SplitSentences::NoNewlines => write!(f, "nonewlines"),
+        }
+    }
+}
+
+/// Optional parameters of the translation request.  
+/// ``glossary_id``: Glossary ID to use for the translation  
+/// ``formality``: Formality of the translation  
+/// ``split_sentences``: How the input is split into sentences  
+/// ``concurrency``: Maximum number of batches sent concurrently (0 or 1: sequentially)  
+/// ``extra_params``: Additional parameters appended to the request as they are (e.g. parameters not yet supported by dptran)  
+/// ``show_billed_characters``: Request the number of characters billed for each text  
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TranslateOptions {
+    pub glossary_id: Option<String>,
+    pub formality: Option<Formality>,
+    pub split_sentences: Option<SplitSentences>,
+    pub concurrency: usize,
+    pub extra_params: Vec<(String, String)>,
+    pub show_billed_characters: bool,
+}
+
+impl TranslateOptions {
+    /// Optional parameters with nothing specified.
+    /// Use the ``with_*`` methods to set the parameters.
+    pub fn new() -> TranslateOptions {
+        TranslateOptions::default()
+    }
+
+    /// Set the glossary ID.
+    pub fn with_glossary_id(mut self, glossary_id: impl Into<String>) -> TranslateOptions {
+        self.glossary_id = Some(glossary_id.into());
+        self
+    }
+
+    /// Set the formality.
+    pub fn with_formality(mut self, formality: Formality) -> TranslateOptions {
+        self.formality = Some(formality);
+        self
+    }
+
+    /// Set how the input is split into sentences.
+    pub fn with_split_sentences(mut self, split_sentences: SplitSentences) -> TranslateOptions {
+        self.split_sentences = Some(split_sentences);
+        self
+    }
+
+    /// Add a parameter appended to the request as it is.
+    pub fn with_param(mut self, key: impl Into<String>, value: impl Into<String>) -> TranslateOptions {
+        self.extra_params.push((key.into(), value.into()));
+        self
+    }
+
+    /// Set the maximum number of batches sent concurrently.
+    pub fn with_concurrency(mut self, concurrency: usize) -> TranslateOptions {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Request the number of characters billed for each text.
+    pub fn with_show_billed_characters(mut self, show_billed_characters: bool) -> TranslateOptions {
+        self.show_billed_characters = show_billed_characters;
+        self
+    }
+}
+
+impl DeeplAPIError {
+    /// Get the full content of ``JsonError`` without truncation.
+    /// Returns None for the other errors.
+    pub fn json_error_content(&self) -> Option<&str> {
+        match self {
+            DeeplAPIError::JsonError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Override the endpoint (None: restore the default).
+fn set_endpoint(endpoint: &RwLock<Option<String>>, url: Option<&str>) {
+    *endpoint.write().unwrap_or_else(|e| e.into_inner()) = url.map(|url| url.to_string());
+}
+
+/// Get the URL of the endpoint, or the default if not overridden.
+fn endpoint_url(endpoint: &RwLock<Option<String>>, default: &str) -> String {
+    endpoint.read().unwrap_or_else(|e| e.into_inner()).clone().unwrap_or(default.to_string())
+}
+
+/// Override the endpoint of the translation (None: ``DEEPL_API_TRANSLATE``).
+pub fn set_translate_endpoint(url: Option<&str>) {
+    set_endpoint(&TRANSLATE_ENDPOINT, url);
+}
+
+/// Override the endpoint of the usage (None: ``DEEPL_API_USAGE``).
+pub fn set_usage_endpoint(url: Option<&str>) {
+    set_endpoint(&USAGE_ENDPOINT, url);
+}
+
+/// Override the endpoint of the language list (None: ``DEEPL_API_LANGUAGES``).
+pub fn set_languages_endpoint(url: Option<&str>) {
+    set_endpoint(&LANGUAGES_ENDPOINT, url);
+}
+
+/// Current endpoint of the translation.
+pub fn translate_endpoint() -> String {
+    endpoint_url(&TRANSLATE_ENDPOINT, DEEPL_API_TRANSLATE)
+}
+
+/// Current endpoint of the usage.
+pub fn usage_endpoint() -> String {
+    endpoint_url(&USAGE_ENDPOINT, DEEPL_API_USAGE)
+}
+
+/// Current endpoint of the language list.
+pub fn languages_endpoint() -> String {
+    endpoint_url(&LANGUAGES_ENDPOINT, DEEPL_API_LANGUAGES)
+}
+
 /// Translation
 /// Returns an error if it fails
-fn request_translate(auth_key: &String, text: Vec<String>, target_lang: &String, source_lang: &Option<String>) -> Result<String, connection::ConnectionError> {
-    let url = DEEPL_API_TRANSLATE.to_string();
+fn request_translate(auth_key: &String, text: Vec<String>, target_lang: &String, source_lang: &Option<String>, options: &TranslateOptions) -> Result<String, connection::ConnectionError> {
+    let url = translate_endpoint();
+    let query = translate_query(auth_key, text, target_lang, source_lang, options);
+    connection::send_and_get(url, query)
+}
+
+/// Percent-encode the key or the value of the form body.
+/// Everything except the unreserved characters (RFC 3986) is encoded, so ``&``, ``=`` and ``+`` are sent as they are.
+fn url_encode(s: impl fmt::Display) -> String {
+    let mut encoded = String::new();
+    for byte in s.to_string().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Build the query of the translation request.
+/// The keys and the values are URL-encoded.
+fn translate_query(auth_key: &String, text: Vec<String>, target_lang: &String, source_lang: &Option<String>, options: &TranslateOptions) -> String {
     let mut query = if source_lang.is_none() {
-        format!("auth_key={}&target_lang={}", auth_key, target_lang)
+        format!("auth_key={}&target_lang={}", url_encode(auth_key), url_encode(target_lang))
     } else {
-        format!("auth_key={}&target_lang={}&source_lang={}", auth_key, target_lang, source_lang.as_ref().unwrap())
+        format!("auth_key={}&target_lang={}&source_lang={}", url_encode(auth_key), url_encode(target_lang), url_encode(source_lang.as_ref().unwrap()))
     };
+    if let Some(glossary_id) = &options.glossary_id {
+        query = format!("{}&glossary_id={}", query, url_encode(glossary_id));
+    }
+    if let Some(formality) = &options.formality {
+        query = format!("{}&formality={}", query, url_encode(formality));
+    }
+    if let Some(split_sentences) = &options.split_sentences {
+        query = format!("{}&split_sentences={}", query, url_encode(split_sentences));
+    }
+    if options.show_billed_characters {
+        query = format!("{}&show_billed_characters=1", query);
+    }
+    for (key, value) in &options.extra_params {
+        query = format!("{}&{}={}", query, url_encode(key), url_encode(value));
+    }
 
     for t in text {
-        query = format!("{}&text={}", query, t);
+        query = format!("{}&text={}", query, url_encode(t));
     }
-    
-    connection::send_and_get(url, query)
+    query
 }
 
 /// Parses the translation results passed in json format,
 ///   stores the translation in a vector, and returns it.
 fn json_to_vec(json: &String) -> Result<Vec<String>, DeeplAPIError> {
+    Ok(json_to_detailed(json)?.into_iter().map(|(text, _, _)| text).collect())
+}
+
+/// Parses the translation results passed in json format with the detected source languages and the billed characters.
+fn json_to_detailed(json: &String) -> Result<Vec<TranslationDetail>, DeeplAPIError> {
     let json: serde_json::Value = serde_json::from_str(&json).map_err(|e| DeeplAPIError::JsonError(e.to_string()))?;
     json.get("translations").ok_or(io::Error::new(io::ErrorKind::Other, "Invalid response")).map_err(|e| DeeplAPIError::JsonError(e.to_string()))?;
     let translations = &json["translations"];
 
     let mut translated_texts = Vec::new();
     for translation in translations.as_array().expect("failed to get array") {
-        let len = translation["text"].to_string().len();
-        let translation_trimmed= translation["text"].to_string()[1..len-1].to_string();
-        translated_texts.push(translation_trimmed);
+        // as_str() returns the unescaped text (e.g. \" -> ")
+        let text = translation["text"].as_str().ok_or(DeeplAPIError::JsonError("Invalid response".to_string()))?;
+        let detected_source_language = translation["detected_source_language"].as_str().map(|lang| lang.to_string());
+        // Only returned with show_billed_characters
+        let billed_characters = translation["billed_characters"].as_u64();
+        translated_texts.push((text.to_string(), detected_source_language, billed_characters));
     }
 
     Ok(translated_texts)
@@ -93,15 +334,117 @@ fn json_to_vec(json: &String) -> Result<Vec<String>, DeeplAPIError> {
 /// Return translation results.
 /// Receive translation results in json format and display translation results.
 /// Return error if json parsing fails.
+/// The results are in the same order as the texts.
 pub fn translate(api_key: &String, text: Vec<String>, target_lang: &String, source_lang: &Option<String>) -> Result<Vec<String>, DeeplAPIError> {
+    translate_partial(api_key, text, target_lang, source_lang, &TranslateOptions::default()).map_err(|(_, e)| e)
+}
+
+/// Return translation results, sending the texts in batches of up to ``DEEPL_API_TRANSLATE_MAX_TEXTS``.
+/// Up to ``options.concurrency`` batches are sent concurrently.
+/// If a batch fails, the translation stops there and
+///   the results of the preceding batches are returned together with the error.
+/// Empty or whitespace-only texts are not sent, and their results are empty strings.
+pub fn translate_partial(api_key: &String, text: Vec<String>, target_lang: &String, source_lang: &Option<String>, options: &TranslateOptions) -> Result<Vec<String>, (Vec<String>, DeeplAPIError)> {
+    skip_blank_texts(text, |text| {
+        translate_in_batches(text, options.concurrency, |batch| translate_batch(api_key, batch, target_lang, source_lang, options))
+    })
+}
+
+/// Translate only the texts that are not empty or whitespace-only with ``translate``,
+///   and re-insert empty strings at the positions of the others so that the results stay aligned with the texts.
+/// On failure, the results are aligned up to the first text that was not translated.
+fn skip_blank_texts<T, F>(text: Vec<String>, translate: F) -> Result<Vec<T>, (Vec<T>, DeeplAPIError)>
+    where T: Default, F: FnOnce(Vec<String>) -> Result<Vec<T>, (Vec<T>, DeeplAPIError)> {
+    let blank = text.iter().map(|t| t.trim().is_empty()).collect::<Vec<bool>>();
+    let restore = |translated: Vec<T>| {
+        let mut translated = translated.into_iter();
+        let mut restored = Vec::with_capacity(blank.len());
+        for &is_blank in &blank {
+            if is_blank {
+                restored.push(T::default());
+            } else if let Some(t) = translated.next() {
+                restored.push(t);
+            } else {
+                break;
+            }
+        }
+        restored
+    };
+    let sent = text.into_iter().filter(|t| !t.trim().is_empty()).collect::<Vec<String>>();
+    translate(sent).map(restore).map_err(|(translated, e)| (restore(translated), e))
+}
+
+/// Translation result with the source language detected by DeepL API and the characters billed for the text.
+pub type TranslationDetail = (String, Option<String>, Option<u64>);
+
+/// Return translation results with the detected source languages and the billed characters.
+/// Same as ``translate_partial()``, including the partial results on failure.
+/// The detected source language and the billed characters of the empty or whitespace-only texts are None.
+pub fn translate_partial_detailed(api_key: &String, text: Vec<String>, target_lang: &String, source_lang: &Option<String>, options: &TranslateOptions) -> Result<Vec<TranslationDetail>, (Vec<TranslationDetail>, DeeplAPIError)> {
+    skip_blank_texts(text, |text| {
+        translate_in_batches(text, options.concurrency, |batch| translate_batch_detailed(api_key, batch, target_lang, source_lang, options))
+    })
+}
+
+/// Return translation results with the detected source languages and the billed characters.
+/// Same as ``translate_partial_detailed()``, but the partial results are not returned on failure.
+pub fn translate_detailed(api_key: &String, text: Vec<String>, target_lang: &String, source_lang: &Option<String>, options: &TranslateOptions) -> Result<Vec<TranslationDetail>, DeeplAPIError> {
+    translate_partial_detailed(api_key, text, target_lang, source_lang, options).map_err(|(_, e)| e)
+}
+
+/// Get the largest size (bytes) of the body of a single translation request.
+/// The body is built in the same way as ``request_translate()`` (URL-encoded, with the other parameters),
+///   and the empty or whitespace-only texts are not counted as they are not sent.
+pub fn max_request_size(auth_key: &String, text: &[String], target_lang: &String, source_lang: &Option<String>, options: &TranslateOptions) -> usize {
+    let sent = text.iter().filter(|t| !t.trim().is_empty()).cloned().collect::<Vec<String>>();
+    sent.chunks(DEEPL_API_TRANSLATE_MAX_TEXTS).map(|batch| translate_query(auth_key, batch.to_vec(), target_lang, source_lang, options).len()).max().unwrap_or(0)
+}
+
+/// Split the texts into batches and translate them with ``request``.
+/// Up to ``concurrency`` batches are requested at the same time, and the results are reassembled in order.
+fn translate_in_batches<T, F>(text: Vec<String>, concurrency: usize, request: F) -> Result<Vec<T>, (Vec<T>, DeeplAPIError)>
+    where T: Send, F: Fn(Vec<String>) -> Result<Vec<T>, DeeplAPIError> + Sync {
+    let batches = text.chunks(DEEPL_API_TRANSLATE_MAX_TEXTS).map(|batch| batch.to_vec()).collect::<Vec<Vec<String>>>();
+    let mut translated_texts = Vec::with_capacity(text.len());
+    for group in batches.chunks(concurrency.max(1)) {
+        let results = if group.len() == 1 {
+            vec![request(group[0].clone())]
+        } else {
+            let request = &request;
+            std::thread::scope(|s| {
+                let handles = group.iter().map(|batch| s.spawn(move || request(batch.clone()))).collect::<Vec<_>>();
+                handles.into_iter().map(|handle| handle.join().expect("translation thread panicked")).collect::<Vec<_>>()
+            })
+        };
+        for result in results {
+            match result {
+                Ok(res) => translated_texts.extend(res),
+                Err(e) => return Err((translated_texts, e)),
+            }
+        }
+    }
+    Ok(translated_texts)
+}
+
+/// Translate a single batch of texts.
+fn translate_batch(api_key: &String, text: Vec<String>, target_lang: &String, source_lang: &Option<String>, options: &TranslateOptions) -> Result<Vec<String>, DeeplAPIError> {
+    let res = request_translate_batch(api_key, text, target_lang, source_lang, options)?;
+    json_to_vec(&res)
+}
+
+/// Translate a single batch of texts with the detected source languages and the billed characters.
+fn translate_batch_detailed(api_key: &String, text: Vec<String>, target_lang: &String, source_lang: &Option<String>, options: &TranslateOptions) -> Result<Vec<TranslationDetail>, DeeplAPIError> {
+    let res = request_translate_batch(api_key, text, target_lang, source_lang, options)?;
+    json_to_detailed(&res)
+}
+
+/// Get json of the translation result of a single batch with request_translate().
+fn request_translate_batch(api_key: &String, text: Vec<String>, target_lang: &String, source_lang: &Option<String>, options: &TranslateOptions) -> Result<String, DeeplAPIError> {
     let auth_key = api_key;
 
-    // Get json of translation result with request_translate().
-    let res = request_translate(&auth_key, text, target_lang, source_lang);
+    let res = request_translate(auth_key, text, target_lang, source_lang, options);
     match res {
-        Ok(res) => {
-            json_to_vec(&res)
-        },
+        Ok(res) => Ok(res),
         // Error message if translation result is not successful
         // DeepL If the API is an error code with a specific meaning, detect it here
         // https://www.deepl.com/en/docs-api/api-access/error-handling/
@@ -119,31 +462,78 @@ pub fn translate(api_key: &String, text: Vec<String>, target_lang: &String, sour
 /// Get the number of characters remaining to be translated.
 /// Retrieved from <https://api-free.deepl.com/v2/usage>.
 /// Returns an error if acquisition fails.
-pub fn get_usage(api_key: &String) -> Result<(u64, u64), DeeplAPIError> {
-    let url = DEEPL_API_USAGE.to_string();
-    let query = format!("auth_key={}", api_key);
+/// Returns (character_count, character_limit, document_count, document_limit).
+/// The document count and limit are only returned for some plans.
+pub fn get_usage(api_key: &String) -> Result<(u64, u64, Option<u64>, Option<u64>), DeeplAPIError> {
+    let url = usage_endpoint();
+    let query = format!("auth_key={}", url_encode(api_key));
     let res = connection::send_and_get(url, query).map_err(|e| DeeplAPIError::ConnectionError(e))?;
-    let v: Value = serde_json::from_str(&res).map_err(|e| DeeplAPIError::JsonError(e.to_string()))?;
+    json_to_usage(&res)
+}
+
+/// Check that the endpoint responds.
+/// The request is sent without the API key, so no characters are consumed.
+/// Returns the HTTP status code of the response (usually an error status such as 403).
+pub fn check_endpoint(url: &str) -> Result<u32, DeeplAPIError> {
+    connection::send_and_get_status(url.to_string(), String::new()).map_err(DeeplAPIError::ConnectionError)
+}
+
+/// Parses the usage passed in json format.
+fn json_to_usage(json: &str) -> Result<(u64, u64, Option<u64>, Option<u64>), DeeplAPIError> {
+    let v: Value = serde_json::from_str(json).map_err(|e| DeeplAPIError::JsonError(e.to_string()))?;
 
     v.get("character_count").ok_or("failed to get character_count".to_string()).map_err(|e| DeeplAPIError::JsonError(e.to_string()))?;
     v.get("character_limit").ok_or("failed to get character_limit".to_string()).map_err(|e| DeeplAPIError::JsonError(e.to_string()))?;
 
     let character_count = v["character_count"].as_u64().expect("failed to get character_count");
     let character_limit = v["character_limit"].as_u64().expect("failed to get character_limit");
-    Ok((character_count, character_limit))
+    // Document usage is optional
+    let document_count = v.get("document_count").and_then(|x| x.as_u64());
+    let document_limit = v.get("document_limit").and_then(|x| x.as_u64());
+    Ok((character_count, character_limit, document_count, document_limit))
 }
 
 /// Get language code list
 /// Retrieved from <https://api-free.deepl.com/v2/languages>.
 pub fn get_language_codes(api_key: &String, type_name: String) -> Result<Vec<LangCodeName>, DeeplAPIError> {
-    let url = DEEPL_API_LANGUAGES.to_string();
-    let query = format!("type={}&auth_key={}", type_name, api_key);
+    let lang_codes = get_language_codes_with_formality(api_key, type_name)?;
+    Ok(lang_codes.into_iter().map(|(lang_code, _)| lang_code).collect())
+}
+
+/// Get language code list with whether each language supports the formality parameter.
+/// The formality support is always false for source languages.
+/// Retrieved from <https://api-free.deepl.com/v2/languages>.
+pub fn get_language_codes_with_formality(api_key: &String, type_name: String) -> Result<Vec<(LangCodeName, bool)>, DeeplAPIError> {
+    let url = languages_endpoint();
+    let query = format!("type={}&auth_key={}", type_name, url_encode(api_key));
     let res = connection::send_and_get(url, query).map_err(|e| DeeplAPIError::ConnectionError(e))?;
-    let v: Value = serde_json::from_str(&res).map_err(|e| DeeplAPIError::JsonError(e.to_string()))?;
 
     let lang_type = if type_name == "source" { LangType::Source } else { LangType::Target };
+    json_to_lang_codes(&res, lang_type)
+}
+
+/// Language code list with the formality support, and the ETag of the response.
+type LangCodesAndEtag = (Vec<(LangCodeName, bool)>, Option<String>);
+
+/// Get language code list with the formality support if it has changed since the ETag.
+/// Returns None if not modified, otherwise the list with its new ETag.
+pub fn get_language_codes_if_modified(api_key: &String, type_name: String, etag: Option<&str>) -> Result<Option<LangCodesAndEtag>, DeeplAPIError> {
+    let url = languages_endpoint();
+    let query = format!("type={}&auth_key={}", type_name, url_encode(api_key));
+    let res = connection::send_and_get_if_none_match(url, query, etag).map_err(DeeplAPIError::ConnectionError)?;
+
+    let lang_type = if type_name == "source" { LangType::Source } else { LangType::Target };
+    match res {
+        ConditionalResponse::NotModified => Ok(None),
+        ConditionalResponse::Modified(json, etag) => Ok(Some((json_to_lang_codes(&json, lang_type)?, etag))),
+    }
+}
 
-    let mut lang_codes: Vec<LangCodeName> = Vec::new();
+/// Parses the language code list passed in json format.
+fn json_to_lang_codes(json: &str, lang_type: LangType) -> Result<Vec<(LangCodeName, bool)>, DeeplAPIError> {
+    let v: Value = serde_json::from_str(json).map_err(|e| DeeplAPIError::JsonError(e.to_string()))?;
+
+    let mut lang_codes: Vec<(LangCodeName, bool)> = Vec::new();
     // Add got language codes
     for value in v.as_array().expect("Invalid response at get_language_codes") {
         value.get("language").ok_or("Invalid response".to_string()).map_err(|e| DeeplAPIError::JsonError(e.to_string()))?;
@@ -153,18 +543,20 @@ pub fn get_language_codes(api_key: &String, type_name: String) -> Result<Vec<Lan
         let lang_name_with_quote = value["name"].to_string();
         let lang_name = &lang_name_with_quote[1..lang_name_with_quote.len()-1];
         let lang_code_pair = (lang_code.to_string(), lang_name.to_string());
-        lang_codes.push(lang_code_pair);
+        // supports_formality is only returned for target languages
+        let supports_formality = value.get("supports_formality").and_then(|x| x.as_bool()).unwrap_or(false);
+        lang_codes.push((lang_code_pair, supports_formality));
     }
     // Add extended language codes
-    for i in 0..EXTENDED_LANG_CODES.len() {
-        if EXTENDED_LANG_CODES[i].2 == lang_type {
-            lang_codes.push((EXTENDED_LANG_CODES[i].0.to_string(), EXTENDED_LANG_CODES[i].1.to_string()));
+    for extended in EXTENDED_LANG_CODES.iter() {
+        if extended.2 == lang_type {
+            lang_codes.push(((extended.0.to_string(), extended.1.to_string()), extended.3));
         }
     }
     // Sort by language code
-    lang_codes.sort_by(|a, b| a.0.cmp(&b.0));
+    lang_codes.sort_by(|a, b| a.0.0.cmp(&b.0.0));
     // return
-    if lang_codes.len() == 0 {
+    if lang_codes.is_empty() {
         Err(DeeplAPIError::GetLanguageCodesError)
     } else {
         Ok(lang_codes)
@@ -176,6 +568,7 @@ pub fn get_language_codes(api_key: &String, type_name: String) -> Result<Vec<Lan
 /// arg[2] : api_key
 /// arg[3] : DeepL API free = 0, DeepL API pro = 1
 fn api_tests() {
+    let _lock = connection::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
     if std::env::args().len() < 3 {
         panic!("Usage: cargo test api_tests -- <api_key> <DeepL API free = 0, DeepL API pro = 1>");
     }
@@ -247,8 +640,96 @@ fn json_to_vec_test() {
     }
 }
 
+#[test]
+fn json_to_detailed_test() {
+    let json = r#"{"translations":[{"detected_source_language":"EN","text":"ハロー"},{"text":"ワールド"}]}"#.to_string();
+    assert_eq!(json_to_detailed(&json).unwrap(), vec![("ハロー".to_string(), Some("EN".to_string()), None), ("ワールド".to_string(), None, None)]);
+    // With show_billed_characters
+    let json = r#"{"translations":[{"detected_source_language":"EN","text":"ハロー","billed_characters":5}]}"#.to_string();
+    assert_eq!(json_to_detailed(&json).unwrap(), vec![("ハロー".to_string(), Some("EN".to_string()), Some(5))]);
+}
+
+#[test]
+fn json_to_vec_unescape_test() {
+    // Quotes, backslashes and line breaks are unescaped
+    let json = r#"{"translations":[{"detected_source_language":"EN","text":"彼は\"こんにちは\"と言った。"},{"detected_source_language":"EN","text":"C:\\dptran\n改行"}]}"#.to_string();
+    assert_eq!(json_to_vec(&json).unwrap(), vec!["彼は\"こんにちは\"と言った。", "C:\\dptran\n改行"]);
+    // Non-string text
+    let json = r#"{"translations":[{"detected_source_language":"EN","text":null}]}"#.to_string();
+    assert!(json_to_vec(&json).is_err());
+}
+
+#[test]
+fn translation_order_test() {
+    // The translations in the response are kept in order
+    let json = r#"{"translations":[{"detected_source_language":"EN","text":"1. 一"},{"detected_source_language":"EN","text":"2. 二"},{"detected_source_language":"EN","text":"3. 三"}]}"#.to_string();
+    assert_eq!(json_to_vec(&json).unwrap(), vec!["1. 一", "2. 二", "3. 三"]);
+
+    // The results of multiple batches are reassembled in order, sequentially and concurrently
+    let text = (0..175).map(|i| format!("{}. item", i)).collect::<Vec<String>>();
+    for concurrency in [1, 4] {
+        let res = translate_in_batches(text.clone(), concurrency, |batch| {
+            Ok(batch.iter().map(|t| t.replace("item", "項目")).collect())
+        }).unwrap();
+        assert_eq!(res.len(), text.len());
+        for (i, t) in res.iter().enumerate() {
+            assert_eq!(t, &format!("{}. 項目", i));
+        }
+    }
+}
+
+#[test]
+fn json_to_usage_test() {
+    let json = r#"{"character_count":180118,"character_limit":1250000,"document_count":2,"document_limit":10}"#.to_string();
+    let res = json_to_usage(&json);
+    match res {
+        Ok(res) => {
+            assert_eq!(res, (180118, 1250000, Some(2), Some(10)));
+        },
+        Err(e) => {
+            panic!("Error: {}", e);
+        }
+    }
+
+    // Without document usage
+    let json = r#"{"character_count":180118,"character_limit":1250000}"#.to_string();
+    assert_eq!(json_to_usage(&json).unwrap(), (180118, 1250000, None, None));
+}
+
+#[test]
+fn json_to_lang_codes_test() {
+    let json = r#"[{"language":"DE","name":"German","supports_formality":true},{"language":"EN-US","name":"English (American)","supports_formality":false}]"#;
+    let res = json_to_lang_codes(json, LangType::Target);
+    match res {
+        Ok(res) => {
+            assert!(res.contains(&(("DE".to_string(), "German".to_string()), true)));
+            assert!(res.contains(&(("EN-US".to_string(), "English (American)".to_string()), false)));
+            // Extended language codes are also added
+            assert!(res.contains(&(("PT".to_string(), "Portuguese".to_string()), true)));
+        },
+        Err(e) => {
+            panic!("Error: {}", e);
+        }
+    }
+}
+
+#[test]
+fn json_error_truncation_test() {
+    let content = "x".repeat(JSON_ERROR_DISPLAY_MAX_CHARS + 100);
+    let e = DeeplAPIError::JsonError(content.clone());
+    let displayed = e.to_string();
+    assert!(displayed.ends_with("…(truncated)"));
+    assert_eq!(displayed, format!("JSON error: {}…(truncated)", "x".repeat(JSON_ERROR_DISPLAY_MAX_CHARS)));
+    assert_eq!(e.json_error_content(), Some(content.as_str()));
+
+    // Short content is displayed as it is
+    let e = DeeplAPIError::JsonError("expected value".to_string());
+    assert_eq!(e.to_string(), "JSON error: expected value");
+}
+
 #[test]
 fn error_test() {
+    let _lock = connection::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
     // no api_key
     let text = vec!["Hello, World!".to_string()];
     let target_lang = "JA".to_string();
@@ -266,3 +747,123 @@ fn error_test() {
     }
 }
 
+#[test]
+fn formality_display_test() {
+    assert_eq!(Formality::Default.to_string(), "default");
+    assert_eq!(Formality::More.to_string(), "more");
+    assert_eq!(Formality::Less.to_string(), "less");
+    assert_eq!(Formality::PreferMore.to_string(), "prefer_more");
+    assert_eq!(Formality::PreferLess.to_string(), "prefer_less");
+    assert_eq!(Formality::More.to_prefer(), Formality::PreferMore);
+    assert_eq!(Formality::Less.to_prefer(), Formality::PreferLess);
+    assert_eq!(Formality::Default.to_prefer(), Formality::Default);
+}
+
+#[test]
+fn translate_query_test() {
+    let options = TranslateOptions { split_sentences: Some(SplitSentences::NoNewlines), ..Default::default() };
+    let query = translate_query(&"key".to_string(), vec!["Hello".to_string()], &"JA".to_string(), &None, &options);
+    assert_eq!(query, "auth_key=key&target_lang=JA&split_sentences=nonewlines&text=Hello");
+    let query = translate_query(&"key".to_string(), vec!["Hello".to_string()], &"JA".to_string(), &None, &TranslateOptions::default());
+    assert!(!query.contains("split_sentences"));
+    // Extra parameters are appended as they are
+    let options = TranslateOptions::new().with_param("model_type", "quality_optimized").with_param("context", "greeting");
+    let query = translate_query(&"key".to_string(), vec!["Hello".to_string()], &"JA".to_string(), &None, &options);
+    assert_eq!(query, "auth_key=key&target_lang=JA&model_type=quality_optimized&context=greeting&text=Hello");
+    // The values containing & and = do not break the query
+    let options = TranslateOptions::new().with_param("context", "Q&A: a=b");
+    let query = translate_query(&"key:fx".to_string(), vec!["Tom & Jerry = 1+1".to_string()], &"JA".to_string(), &None, &options);
+    assert_eq!(query, "auth_key=key%3Afx&target_lang=JA&context=Q%26A%3A%20a%3Db&text=Tom%20%26%20Jerry%20%3D%201%2B1");
+    assert_eq!(url_encode("こんにちは"), "%E3%81%93%E3%82%93%E3%81%AB%E3%81%A1%E3%81%AF");
+    // The billed characters are requested only if specified
+    assert!(!query.contains("show_billed_characters"));
+    let query = translate_query(&"key".to_string(), vec!["Hello".to_string()], &"JA".to_string(), &None, &TranslateOptions::new().with_show_billed_characters(true));
+    assert_eq!(query, "auth_key=key&target_lang=JA&show_billed_characters=1&text=Hello");
+}
+
+#[test]
+fn translate_in_batches_test() {
+    // 60 texts: the first batch (50 texts) succeeds and the second batch (10 texts) fails.
+    let text = (0..60).map(|i| i.to_string()).collect::<Vec<String>>();
+    let res = translate_in_batches(text, 1, |batch| {
+        if batch[0] == "50" {
+            return Err(DeeplAPIError::ConnectionError(connection::ConnectionError::ServiceUnavailable));
+        }
+        Ok(batch.iter().map(|t| format!("translated {}", t)).collect())
+    });
+    match res {
+        Ok(_) => {
+            panic!("Error: translation success");
+        },
+        Err((translated, e)) => {
+            assert_eq!(translated.len(), DEEPL_API_TRANSLATE_MAX_TEXTS);
+            assert_eq!(translated[0], "translated 0");
+            assert_eq!(translated[49], "translated 49");
+            assert_eq!(e, DeeplAPIError::ConnectionError(connection::ConnectionError::ServiceUnavailable));
+        }
+    }
+}
+
+#[test]
+fn skip_blank_texts_test() {
+    let text = vec!["a".to_string(), "".to_string(), "b".to_string()];
+    let res = skip_blank_texts(text, |sent| {
+        // Only the two texts are sent
+        assert_eq!(sent, vec!["a".to_string(), "b".to_string()]);
+        Ok(sent.iter().map(|t| format!("<tr-{}>", t)).collect())
+    }).unwrap();
+    assert_eq!(res, vec!["<tr-a>".to_string(), "".to_string(), "<tr-b>".to_string()]);
+
+    // Failure after the first text: the blank following it is still aligned
+    let text = vec!["a".to_string(), "  ".to_string(), "b".to_string(), "c".to_string()];
+    let res = skip_blank_texts(text, |_| Err((vec!["<tr-a>".to_string()], DeeplAPIError::ConnectionError(connection::ConnectionError::ServiceUnavailable))));
+    assert_eq!(res.unwrap_err().0, vec!["<tr-a>".to_string(), "".to_string()]);
+}
+
+#[test]
+fn translate_in_batches_concurrently_test() {
+    // 230 texts: 5 batches, sent 3 at a time.
+    // Earlier batches take longer so that they finish after the later ones.
+    let text = (0..230).map(|i| i.to_string()).collect::<Vec<String>>();
+    let res = translate_in_batches(text, 3, |batch| {
+        let first = batch[0].parse::<u64>().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis((250 - first) / 10));
+        Ok(batch.iter().map(|t| format!("translated {}", t)).collect())
+    }).unwrap();
+    assert_eq!(res.len(), 230);
+    for (i, t) in res.iter().enumerate() {
+        assert_eq!(t, &format!("translated {}", i));
+    }
+}
+
+#[test]
+fn translate_options_builder_test() {
+    let options = TranslateOptions::new()
+        .with_glossary_id("def3a26b-3e84-45b3-84ae-0c0aaf3525f7")
+        .with_formality(Formality::PreferLess)
+        .with_split_sentences(SplitSentences::NoNewlines)
+        .with_concurrency(4);
+    assert_eq!(options.glossary_id, Some("def3a26b-3e84-45b3-84ae-0c0aaf3525f7".to_string()));
+    assert_eq!(options.formality, Some(Formality::PreferLess));
+    assert_eq!(options.split_sentences, Some(SplitSentences::NoNewlines));
+    assert_eq!(options.concurrency, 4);
+    assert_eq!(TranslateOptions::new(), TranslateOptions::default());
+}
+
+#[test]
+fn set_endpoint_test() {
+    // The endpoints are shared with the other tests using the connection
+    let _lock = connection::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    assert_eq!(usage_endpoint(), DEEPL_API_USAGE);
+
+    // Only the usage endpoint is overridden
+    set_usage_endpoint(Some("http://127.0.0.1:8080/v2/usage"));
+    let (usage, translate, languages) = (usage_endpoint(), translate_endpoint(), languages_endpoint());
+    set_usage_endpoint(None);
+    assert_eq!(usage, "http://127.0.0.1:8080/v2/usage");
+    assert_eq!(translate, DEEPL_API_TRANSLATE);
+    assert_eq!(languages, DEEPL_API_LANGUAGES);
+
+    // Restored to the default
+    assert_eq!(usage_endpoint(), DEEPL_API_USAGE);
+}
@@ -2,7 +2,34 @@
 
 use std::str;
 use std::fmt;
-use curl::easy::Easy;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::Duration;
+use curl::easy::{Easy, List};
+
+/// Whether to skip verification of the TLS certificate. See ``set_accept_invalid_certs()``.
+static ACCEPT_INVALID_CERTS: AtomicBool = AtomicBool::new(false);
+/// Timeout of the connection phase in milliseconds (0: curl's default). See ``set_connect_timeout()``.
+static CONNECT_TIMEOUT_MS: AtomicU64 = AtomicU64::new(0);
+/// Timeout of the whole request in milliseconds (0: no timeout). See ``set_request_timeout()``.
+static REQUEST_TIMEOUT_MS: AtomicU64 = AtomicU64::new(0);
+/// Maximum number of retries of a rejected request (0: no retry). See ``set_retry_policy()``.
+static MAX_RETRIES: AtomicU64 = AtomicU64::new(0);
+/// Delay before the first retry in milliseconds. See ``set_retry_policy()``.
+static RETRY_DELAY_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Maximum number of characters of the response body kept in ``ConnectionError::Unexpected``.
+pub const UNEXPECTED_BODY_MAX_CHARS: usize = 200;
+
+/// Lock for the tests changing or depending on the connection settings above
+#[cfg(test)]
+pub(crate) static TEST_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+thread_local! {
+    /// curl::easy session shared by the requests on the same thread.
+    /// Reusing the handle keeps the connection (and TLS session) alive across requests.
+    static SESSION: RefCell<Easy> = RefCell::new(Easy::new());
+}
 
 /// ConnectionError  
 /// It is an error that occurs when communicating with the DeepL API.  
@@ -13,8 +40,11 @@ use curl::easy::Easy;
 /// ``TooManyRequests``: 429 Too Many Requests  
 /// ``UnprocessableEntity``: 456 Unprocessable Entity  
 /// ``ServiceUnavailable``: 503 Service Unavailable  
+/// ``Offline``: Could not resolve the host or connect to it (e.g. no network connection)  
+/// ``Timeout``: The request timed out after the connection was established  
 /// ``CurlError``: Curl Error  
-/// ``UnknownError``: Unknown Error  
+/// ``Unexpected``: Other non-2xx response with its status code and the beginning of the body (up to ``UNEXPECTED_BODY_MAX_CHARS`` characters)  
+/// ``UnknownError``: Unknown Error (e.g. an empty response)  
 #[derive(Debug, PartialEq)]
 pub enum ConnectionError {
     BadRequest,
@@ -24,7 +54,10 @@ pub enum ConnectionError {
     TooManyRequests,
     UnprocessableEntity,
     ServiceUnavailable,
+    Offline(String),
+    Timeout(String),
     CurlError(String),
+    Unexpected(u16, String),
     UnknownError,
 }
 impl fmt::Display for ConnectionError {
@@ -37,38 +70,161 @@ impl fmt::Display for ConnectionError {
             ConnectionError::TooManyRequests => write!(f, "429 Too Many Requests"),
             ConnectionError::UnprocessableEntity => write!(f, "456 Unprocessable Entity"),
             ConnectionError::ServiceUnavailable => write!(f, "503 Service Unavailable"),
+            ConnectionError::Offline(ref e) => write!(f, "Offline: {}", e),
+            ConnectionError::Timeout(ref e) => write!(f, "Timeout: {}", e),
             ConnectionError::CurlError(ref e) => write!(f, "Curl Error: {}", e),
+            ConnectionError::Unexpected(status, ref body) => write!(f, "{} Unexpected Response: {}", status, body),
             ConnectionError::UnknownError => write!(f, "Unknown Error"),
         }
     }
 }
 
+/// Response of a conditional request. See ``send_and_get_if_none_match()``.  
+/// ``Modified``: The resource was returned with its ETag (if any)  
+/// ``NotModified``: 304 Not Modified; the resource has not changed since the ETag  
+#[derive(Debug, PartialEq)]
+pub enum ConditionalResponse {
+    Modified(String, Option<String>),
+    NotModified,
+}
+
+/// Run ``f`` with the curl::easy session of the current thread.
+fn with_session<R>(f: impl FnOnce(&mut Easy) -> R) -> R {
+    SESSION.with(|session| f(&mut session.borrow_mut()))
+}
+
+/// Accept invalid (e.g. self-signed) TLS certificates in the following requests.
+/// This is unsafe: the connection is no longer protected against man-in-the-middle attacks.
+pub fn set_accept_invalid_certs(accept: bool) {
+    ACCEPT_INVALID_CERTS.store(accept, Ordering::Relaxed);
+}
+
+/// Set the timeout of the connection phase in the following requests.
+/// None uses curl's default (300 seconds).
+pub fn set_connect_timeout(timeout: Option<Duration>) {
+    CONNECT_TIMEOUT_MS.store(timeout.map_or(0, |t| t.as_millis() as u64), Ordering::Relaxed);
+}
+
+/// Set the timeout of the whole request (including the connection phase) in the following requests.
+/// None means no timeout.
+pub fn set_request_timeout(timeout: Option<Duration>) {
+    REQUEST_TIMEOUT_MS.store(timeout.map_or(0, |t| t.as_millis() as u64), Ordering::Relaxed);
+}
+
+/// Set the retry policy of the following requests.
+/// A request rejected with 429 Too Many Requests or 503 Service Unavailable (e.g. during maintenance) is sent again up to max_retries times,
+/// waiting for delay before the first retry and doubling it for each further retry.
+/// The other errors are never retried: the request may have been processed (and billed) on the server.
+pub fn set_retry_policy(max_retries: u32, delay: Duration) {
+    MAX_RETRIES.store(max_retries as u64, Ordering::Relaxed);
+    RETRY_DELAY_MS.store(delay.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// Whether the request can be sent again safely after the error.
+fn is_retryable(e: &ConnectionError) -> bool {
+    *e == ConnectionError::TooManyRequests || *e == ConnectionError::ServiceUnavailable
+}
+
+/// Send the request, retrying it according to the retry policy.
+fn with_retries<T>(send: impl FnMut() -> Result<T, ConnectionError>) -> Result<T, ConnectionError> {
+    with_retries_of(MAX_RETRIES.load(Ordering::Relaxed), Duration::from_millis(RETRY_DELAY_MS.load(Ordering::Relaxed)), send)
+}
+
+/// Send the request, retrying it up to max_retries times with the exponential backoff from delay.
+fn with_retries_of<T>(max_retries: u64, mut delay: Duration, mut send: impl FnMut() -> Result<T, ConnectionError>) -> Result<T, ConnectionError> {
+    let mut retries = 0;
+    loop {
+        match send() {
+            Err(e) if retries < max_retries && is_retryable(&e) => {
+                std::thread::sleep(delay);
+                delay = delay.saturating_mul(2);
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
 /// Preparing curl::easy
-fn make_session(url: String, post_data: String) -> Result<Easy, String> {
-    let mut easy = Easy::new();
-    easy.url(url.as_str()).map_err(|e| e.to_string())?;
-    easy.post(true).map_err(|e| e.to_string())?;
-    easy.post_fields_copy(post_data.as_bytes()).map_err(|e| e.to_string())?;
-    Ok(easy)
+/// The headers are always set, so that the headers of the previous request on the reused session are not sent again.
+fn make_session(easy: &mut Easy, url: String, post_data: String) -> Result<(), curl::Error> {
+    make_session_with_headers(easy, url, post_data, List::new())
+}
+
+/// Preparing curl::easy with the additional request headers
+fn make_session_with_headers(easy: &mut Easy, url: String, post_data: String, headers: List) -> Result<(), curl::Error> {
+    easy.http_headers(headers)?;
+    let verify = !ACCEPT_INVALID_CERTS.load(Ordering::Relaxed);
+    easy.ssl_verify_peer(verify)?;
+    easy.ssl_verify_host(verify)?;
+    // The session is reused, so the timeouts are always set (0 resets them to the defaults)
+    easy.connect_timeout(Duration::from_millis(CONNECT_TIMEOUT_MS.load(Ordering::Relaxed)))?;
+    easy.timeout(Duration::from_millis(REQUEST_TIMEOUT_MS.load(Ordering::Relaxed)))?;
+    easy.url(url.as_str())?;
+    easy.post(true)?;
+    easy.post_fields_copy(post_data.as_bytes())?;
+    Ok(())
 }
 
 /// Sending and Receiving
-fn transfer(mut easy: Easy) -> Result<(Vec<u8>, u32), String> {
+/// Returns the body, the response code and the response headers.
+fn transfer(easy: &mut Easy) -> Result<(Vec<u8>, u32, Vec<String>), curl::Error> {
     let mut dst = Vec::new();
+    let mut headers = Vec::new();
     {
         let mut transfer = easy.transfer();
         transfer.write_function(|data| {
             dst.extend_from_slice(data);
             Ok(data.len())
-        }).map_err(|e| e.to_string())?;
-        transfer.perform().map_err(|e| e.to_string())?;
+        })?;
+        transfer.header_function(|header| {
+            headers.push(String::from_utf8_lossy(header).trim_end().to_string());
+            true
+        })?;
+        transfer.perform()?;
+    }
+    let response_code = easy.response_code()?;
+    Ok((dst, response_code, headers))
+}
+
+/// Get the value of the header (case-insensitive).
+fn header_value(headers: &[String], name: &str) -> Option<String> {
+    headers.iter().find_map(|header| {
+        let (key, value) = header.split_once(':')?;
+        if key.trim().eq_ignore_ascii_case(name) {
+            Some(value.trim().to_string())
+        } else {
+            None
+        }
+    })
+}
+
+/// Whether the connection to the server was established in the last transfer of the session.
+/// curl reports the connect time as zero until the connection is completed.
+fn is_connected(easy: &mut Easy) -> bool {
+    easy.connect_time().map(|t| !t.is_zero()).unwrap_or(false)
+}
+
+/// Distinguish the failures to reach the server (offline, DNS resolution, connection refused, connect timeout) from the other curl errors.
+/// connected: whether the connection to the server was established before the failure
+fn curl_error(e: curl::Error, connected: bool) -> ConnectionError {
+    if e.is_couldnt_resolve_host() || e.is_couldnt_resolve_proxy() || e.is_couldnt_connect() {
+        ConnectionError::Offline(e.to_string())
+    } else if e.is_operation_timedout() {
+        // curl uses the same error code for both timeouts, so the phase is told by the connection
+        if connected {
+            ConnectionError::Timeout(e.to_string())
+        } else {
+            ConnectionError::Offline(e.to_string())
+        }
+    } else {
+        ConnectionError::CurlError(e.to_string())
     }
-    let response_code = easy.response_code().map_err(|e| e.to_string())?;
-    Ok((dst, response_code))
 }
 
 /// Error statement generation
-fn handle_error(response_code: u32) -> ConnectionError {
+/// The responses without a specialized variant are reported with the status code and the body.
+fn handle_error(response_code: u32, body: &[u8]) -> ConnectionError {
     match response_code {
         400 => ConnectionError::BadRequest,
         403 => ConnectionError::Forbidden,
@@ -77,26 +233,300 @@ fn handle_error(response_code: u32) -> ConnectionError {
         429 => ConnectionError::TooManyRequests,
         456 => ConnectionError::UnprocessableEntity,
         503 => ConnectionError::ServiceUnavailable,
-        _ => ConnectionError::UnknownError,
+        _ => ConnectionError::Unexpected(response_code as u16, body_excerpt(body)),
+    }
+}
+
+/// Beginning of the response body, e.g. to report an unexpected response without keeping a whole HTML page.
+fn body_excerpt(body: &[u8]) -> String {
+    let body = String::from_utf8_lossy(body);
+    if body.chars().count() > UNEXPECTED_BODY_MAX_CHARS {
+        format!("{}…(truncated)", body.chars().take(UNEXPECTED_BODY_MAX_CHARS).collect::<String>())
+    } else {
+        body.to_string()
     }
 }
 
+/// Whether the response code is 2xx.
+fn is_success(response_code: u32) -> bool {
+    (200..300).contains(&response_code)
+}
+
 /// Communicate with the DeepL API.
 pub fn send_and_get(url: String, post_data: String) -> Result<String, ConnectionError> {
-    let easy = match make_session(url, post_data) {
-        Ok(easy) => easy,
-        Err(e) => return Err(ConnectionError::CurlError(e)),
-    };
-    let (dst, response_code) = match transfer(easy) {
-        Ok((dst, response_code)) => (dst, response_code),
-        Err(e) => return Err(ConnectionError::CurlError(e)),
-    };
-
-    if dst.len() > 0 {
+    with_retries(|| send_and_get_once(url.clone(), post_data.clone()))
+}
+
+/// Communicate with the DeepL API once.
+fn send_and_get_once(url: String, post_data: String) -> Result<String, ConnectionError> {
+    let result = with_session(|easy| {
+        make_session(easy, url, post_data).map_err(|e| curl_error(e, false))?;
+        transfer(easy).map_err(|e| curl_error(e, is_connected(easy)))
+    });
+    let (dst, response_code, _) = result?;
+
+    if !is_success(response_code) {
+        // HTTP Error Handling
+        Err(handle_error(response_code, &dst))
+    } else if !dst.is_empty() {
         let s = str::from_utf8(&dst).expect("Invalid UTF-8");
         Ok(s.to_string())
     } else {
+        Err(ConnectionError::UnknownError)
+    }
+}
+
+/// Send a request once and get the response code, whatever it is.
+/// Only the failures to get a response (offline, TLS error, timeout, ...) are returned as errors.
+pub fn send_and_get_status(url: String, post_data: String) -> Result<u32, ConnectionError> {
+    let result = with_session(|easy| {
+        make_session(easy, url, post_data).map_err(|e| curl_error(e, false))?;
+        transfer(easy).map_err(|e| curl_error(e, is_connected(easy)))
+    });
+    let (_, response_code, _) = result?;
+    Ok(response_code)
+}
+
+/// Communicate with the DeepL API with ``If-None-Match``.
+/// If the resource has not changed since the ETag, ``ConditionalResponse::NotModified`` is returned.
+pub fn send_and_get_if_none_match(url: String, post_data: String, etag: Option<&str>) -> Result<ConditionalResponse, ConnectionError> {
+    with_retries(|| send_and_get_if_none_match_once(url.clone(), post_data.clone(), etag))
+}
+
+/// Communicate with the DeepL API with ``If-None-Match`` once.
+fn send_and_get_if_none_match_once(url: String, post_data: String, etag: Option<&str>) -> Result<ConditionalResponse, ConnectionError> {
+    let result = with_session(|easy| {
+        let mut headers = List::new();
+        if let Some(etag) = etag {
+            headers.append(&format!("If-None-Match: {}", etag)).map_err(|e| curl_error(e, false))?;
+        }
+        make_session_with_headers(easy, url, post_data, headers).map_err(|e| curl_error(e, false))?;
+        transfer(easy).map_err(|e| curl_error(e, is_connected(easy)))
+    });
+    let (dst, response_code, headers) = result?;
+
+    if response_code == 304 {
+        Ok(ConditionalResponse::NotModified)
+    } else if !is_success(response_code) {
         // HTTP Error Handling
-        Err(handle_error(response_code))
+        Err(handle_error(response_code, &dst))
+    } else if !dst.is_empty() {
+        let s = str::from_utf8(&dst).expect("Invalid UTF-8");
+        Ok(ConditionalResponse::Modified(s.to_string(), header_value(&headers, "ETag")))
+    } else {
+        Err(ConnectionError::UnknownError)
     }
 }
+
+#[test]
+fn session_reuse_test() {
+    // The same curl handle is used across requests on the same thread
+    let first = with_session(|easy| {
+        make_session(easy, "https://api-free.deepl.com/v2/usage".to_string(), "auth_key=dummy".to_string()).unwrap();
+        easy.raw() as usize
+    });
+    let second = with_session(|easy| {
+        make_session(easy, "https://api-free.deepl.com/v2/languages".to_string(), "auth_key=dummy".to_string()).unwrap();
+        easy.raw() as usize
+    });
+    assert_eq!(first, second);
+
+    // Another thread has its own handle
+    let other = std::thread::spawn(|| with_session(|easy| easy.raw() as usize)).join().unwrap();
+    assert_ne!(first, other);
+}
+
+#[test]
+fn accept_invalid_certs_test() {
+    let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    set_accept_invalid_certs(true);
+    let res = with_session(|easy| make_session(easy, "https://localhost:8443/v2/usage".to_string(), "auth_key=dummy".to_string()));
+    set_accept_invalid_certs(false);
+    assert_eq!(res, Ok(()));
+}
+
+#[test]
+fn offline_test() {
+    let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    // Nothing listens on port 1 of localhost
+    let res = send_and_get("http://127.0.0.1:1/v2/usage".to_string(), "auth_key=dummy".to_string());
+    match res {
+        Err(ConnectionError::Offline(_)) => {},
+        _ => panic!("Error: not detected as offline: {:?}", res),
+    }
+}
+
+#[test]
+fn timeout_error_test() {
+    let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    use std::net::TcpListener;
+
+    // CURLE_OPERATION_TIMEDOUT is told by the connection, not by the message of libcurl
+    assert!(matches!(curl_error(curl::Error::new(28), false), ConnectionError::Offline(_)));
+    assert!(matches!(curl_error(curl::Error::new(28), true), ConnectionError::Timeout(_)));
+    // Connection refused
+    assert!(matches!(curl_error(curl::Error::new(7), false), ConnectionError::Offline(_)));
+
+    // Server accepting the connection but never responding
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/v2/usage", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        std::thread::sleep(Duration::from_millis(500));
+        drop(stream);
+    });
+    set_request_timeout(Some(Duration::from_millis(100)));
+    let res = send_and_get(url, "auth_key=dummy".to_string());
+    set_request_timeout(None);
+    assert!(matches!(res, Err(ConnectionError::Timeout(_))), "not detected as timeout: {:?}", res);
+    server.join().unwrap();
+
+    // The timeouts are applied to the session
+    set_connect_timeout(Some(Duration::from_millis(100)));
+    set_request_timeout(Some(Duration::from_secs(30)));
+    let res = with_session(|easy| make_session(easy, "https://api-free.deepl.com/v2/usage".to_string(), "auth_key=dummy".to_string()));
+    set_connect_timeout(None);
+    set_request_timeout(None);
+    assert_eq!(res, Ok(()));
+}
+
+#[test]
+fn if_none_match_test() {
+    let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    // Server returning 304 if If-None-Match matches the ETag
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/v2/languages", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let mut requests = Vec::new();
+        for _ in 0..2 {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 4096];
+            let n = stream.read(&mut buf).unwrap();
+            let request = String::from_utf8_lossy(&buf[..n]).to_string();
+            let response = if request.contains("If-None-Match: \"v1\"") {
+                "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n".to_string()
+            } else {
+                let body = r#"[{"language":"JA","name":"Japanese"}]"#;
+                format!("HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+            };
+            stream.write_all(response.as_bytes()).unwrap();
+            requests.push(request);
+        }
+        requests
+    });
+
+    let first = send_and_get_if_none_match(url.clone(), "type=target&auth_key=dummy".to_string(), None).unwrap();
+    assert_eq!(first, ConditionalResponse::Modified(r#"[{"language":"JA","name":"Japanese"}]"#.to_string(), Some("\"v1\"".to_string())));
+    let second = send_and_get_if_none_match(url, "type=target&auth_key=dummy".to_string(), Some("\"v1\"")).unwrap();
+    assert_eq!(second, ConditionalResponse::NotModified);
+
+    let requests = server.join().unwrap();
+    assert!(!requests[0].contains("If-None-Match"));
+    assert!(requests[1].contains("If-None-Match: \"v1\""));
+}
+
+#[test]
+fn unexpected_status_test() {
+    let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    // Server returning 418 with a body
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/v2/usage", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buf = [0; 4096];
+        let _ = stream.read(&mut buf).unwrap();
+        let body = "I'm a teapot";
+        let response = format!("HTTP/1.1 418 I'm a teapot\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+        stream.write_all(response.as_bytes()).unwrap();
+    });
+
+    let res = send_and_get(url, "auth_key=dummy".to_string());
+    assert_eq!(res, Err(ConnectionError::Unexpected(418, "I'm a teapot".to_string())));
+    server.join().unwrap();
+
+    // The specialized variants are kept for the common cases
+    assert_eq!(handle_error(403, b"{}"), ConnectionError::Forbidden);
+    assert_eq!(handle_error(456, b""), ConnectionError::UnprocessableEntity);
+    // Only the beginning of a long body is kept
+    let body = "x".repeat(UNEXPECTED_BODY_MAX_CHARS + 100);
+    assert_eq!(handle_error(502, body.as_bytes()), ConnectionError::Unexpected(502, format!("{}…(truncated)", "x".repeat(UNEXPECTED_BODY_MAX_CHARS))));
+}
+
+#[test]
+fn retry_test() {
+    let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    // Server returning 429 twice, then 200
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/v2/usage", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        for i in 0..3 {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 4096];
+            let _ = stream.read(&mut buf).unwrap();
+            let response = if i < 2 {
+                "HTTP/1.1 429 Too Many Requests\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+            } else {
+                let body = r#"{"character_count":0,"character_limit":500000}"#;
+                format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+            };
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+
+    // No retry by default
+    assert_eq!(send_and_get(url.clone(), "auth_key=dummy".to_string()), Err(ConnectionError::TooManyRequests));
+    // Retried after the delay
+    set_retry_policy(2, Duration::from_millis(10));
+    let res = send_and_get(url, "auth_key=dummy".to_string());
+    set_retry_policy(0, Duration::ZERO);
+    assert_eq!(res, Ok(r#"{"character_count":0,"character_limit":500000}"#.to_string()));
+    server.join().unwrap();
+
+    // The errors possibly processed on the server are not retried
+    assert!(is_retryable(&ConnectionError::TooManyRequests));
+    assert!(is_retryable(&ConnectionError::ServiceUnavailable));
+    assert!(!is_retryable(&ConnectionError::Timeout("timed out".to_string())));
+}
+
+#[test]
+fn service_unavailable_retry_test() {
+    let _lock = TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    // Server under maintenance: 503 twice, then 200
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/v2/usage", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        for i in 0..3 {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 4096];
+            let _ = stream.read(&mut buf).unwrap();
+            let response = if i < 2 {
+                "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+            } else {
+                let body = r#"{"character_count":0,"character_limit":500000}"#;
+                format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+            };
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+
+    let mut attempts = 0;
+    let res = with_retries_of(2, Duration::from_millis(10), || {
+        attempts += 1;
+        send_and_get_once(url.clone(), "auth_key=dummy".to_string())
+    });
+    assert_eq!(res, Ok(r#"{"character_count":0,"character_limit":500000}"#.to_string()));
+    assert_eq!(attempts, 3);
+    server.join().unwrap();
+}
@@ -1,8 +1,20 @@
 mod deeplapi;
+mod placeholder;
+
+use std::fmt;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
 
 pub use deeplapi::LangCodeName;
 pub use deeplapi::DeeplAPIError;
+pub use deeplapi::JSON_ERROR_DISPLAY_MAX_CHARS;
 pub use deeplapi::ConnectionError;
+pub use deeplapi::TranslateOptions;
+pub use deeplapi::Formality;
+pub use deeplapi::SplitSentences;
+pub use deeplapi::{DEEPL_API_TRANSLATE, DEEPL_API_USAGE, DEEPL_API_LANGUAGES};
+pub use deeplapi::DEEPL_API_REQUEST_MAX_BYTES;
+pub use placeholder::DEFAULT_PLACEHOLDER_PATTERNS;
 
 /// string as language code
 pub type LangCode = String;
@@ -13,6 +25,12 @@ pub type LangCode = String;
 /// ``ApiKeyIsNotSet``: API key is not set  
 /// ``NoTargetLanguageSpecified``: No target language specified  
 /// ``CouldNotGetInputText``: Could not get input text  
+/// ``InvalidPlaceholderPattern``: Invalid placeholder pattern  
+/// ``InvalidGlossaryId``: Invalid glossary ID  
+/// ``GlossaryRequiresSourceLang``: A glossary is used without a source language  
+/// ``FormalityNotSupported``: The target language does not support formality  
+/// ``RequestTooLarge``: The request exceeds the size limit of DeepL API (request size, limit)  
+/// ``SegmentCountMismatch``: The number of translations differs from the number of segments (segments, translations)  
 #[derive(Debug, PartialEq)]
 pub enum DpTranError {
     DeeplApiError(DeeplAPIError),
@@ -20,6 +38,12 @@ pub enum DpTranError {
     ApiKeyIsNotSet,
     NoTargetLanguageSpecified,
     CouldNotGetInputText,
+    InvalidPlaceholderPattern(String),
+    InvalidGlossaryId,
+    GlossaryRequiresSourceLang,
+    FormalityNotSupported,
+    RequestTooLarge(usize, usize),
+    SegmentCountMismatch(usize, usize),
 }
 impl ToString for DpTranError {
     fn to_string(&self) -> String {
@@ -29,12 +53,30 @@ impl ToString for DpTranError {
             DpTranError::ApiKeyIsNotSet => "API key is not set".to_string(),
             DpTranError::NoTargetLanguageSpecified => "No target language specified".to_string(),
             DpTranError::CouldNotGetInputText => "Could not get input text".to_string(),
+            DpTranError::InvalidPlaceholderPattern(e) => format!("Invalid placeholder pattern: {}", e),
+            DpTranError::InvalidGlossaryId => "Invalid glossary ID".to_string(),
+            DpTranError::GlossaryRequiresSourceLang => "A glossary requires the source language to be specified".to_string(),
+            DpTranError::FormalityNotSupported => "The target language does not support formality".to_string(),
+            DpTranError::SegmentCountMismatch(segments, translations) => format!("{} translations were returned for {} segments", translations, segments),
+            DpTranError::RequestTooLarge(size, limit) => format!("The request size ({} bytes) exceeds the limit of DeepL API ({} bytes per request). Split the input into smaller parts", size, limit),
         }
     }
 }
 
+/// Partially completed translation.  
+/// ``translated``: Translation results of the texts completed before the failure  
+/// ``failed_range``: Range of the source texts that were not translated  
+/// ``error``: The error that stopped the translation  
+#[derive(Debug, PartialEq)]
+pub struct DpTranPartialError {
+    pub translated: Vec<String>,
+    pub failed_range: std::ops::Range<usize>,
+    pub error: DpTranError,
+}
+
 /// Target / Source language types  
 /// used in get_language_codes()  
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LangType {
     Target,
     Source,
@@ -44,10 +86,25 @@ pub enum LangType {
 /// character_count: Number of characters translated this month  
 /// character_limit: Maximum number of characters that can be translated this month  
 /// If character_limit is 0, it is unlimited  
+/// document_count: Number of documents translated this month (only for some plans)  
+/// document_limit: Maximum number of documents that can be translated this month (only for some plans)  
 pub struct DpTranUsage {
     pub character_count: u64,
     pub character_limit: u64,
     pub unlimited: bool,
+    pub document_count: Option<u64>,
+    pub document_limit: Option<u64>,
+}
+
+/// Character usage, e.g. ``1234 / 500000 (0%)``, or ``1234 / unlimited`` for unlimited keys  
+impl fmt::Display for DpTranUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.unlimited {
+            write!(f, "{} / unlimited", self.character_count)
+        } else {
+            write!(f, "{} / {} ({}%)", self.character_count, self.character_limit, (self.character_count as f64 / self.character_limit as f64 * 100.0).round())
+        }
+    }
 }
 
 /// Get language code list. Using DeepL API.  
@@ -63,6 +120,65 @@ pub fn get_language_codes(api_key: &String, lang_type: LangType) -> Result<Vec<L
     Ok(lang_codes)
 }
 
+/// Get language code list with whether each language supports the formality parameter. Using DeepL API.  
+/// The formality support is always false for source languages.  
+/// Retrieved from <https://api-free.deepl.com/v2/languages>.  
+/// api_key: DeepL API key  
+/// lang_type: Target or Source  
+pub fn get_language_codes_with_formality(api_key: &String, lang_type: LangType) -> Result<Vec<(LangCodeName, bool)>, DpTranError> {
+    let type_name = match lang_type {
+        LangType::Target => "target".to_string(),
+        LangType::Source => "source".to_string(),
+    };
+    let lang_codes = deeplapi::get_language_codes_with_formality(api_key, type_name).map_err(DpTranError::DeeplApiError)?;
+    Ok(lang_codes)
+}
+
+/// Language code list with the ETag of the response.  
+/// Keep it and pass it to ``get_language_codes_with_etag()`` to avoid downloading the unchanged list again.  
+/// ``etag``: ETag of the response (None if DeepL API did not return it)  
+/// ``lang_codes``: Language codes with whether each language supports the formality parameter  
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LanguageCodesWithEtag {
+    pub etag: Option<String>,
+    pub lang_codes: Vec<(LangCodeName, bool)>,
+}
+
+/// Get language code list with whether each language supports the formality parameter, using a conditional request. Using DeepL API.  
+/// If the cached list has an ETag, it is sent as ``If-None-Match`` and the cached list is returned on 304 Not Modified.  
+/// api_key: DeepL API key  
+/// lang_type: Target or Source  
+/// cached: The list got previously (optional)  
+/// A 304 Not Modified without the cached list is reported as ``ConnectionError::Unexpected``.  
+pub fn get_language_codes_with_etag(api_key: &String, lang_type: LangType, cached: Option<LanguageCodesWithEtag>) -> Result<LanguageCodesWithEtag, DpTranError> {
+    let type_name = match lang_type {
+        LangType::Target => "target".to_string(),
+        LangType::Source => "source".to_string(),
+    };
+    let etag = cached.as_ref().and_then(|cached| cached.etag.clone());
+    match (deeplapi::get_language_codes_if_modified(api_key, type_name, etag.as_deref()).map_err(DpTranError::DeeplApiError)?, cached) {
+        (Some((lang_codes, etag)), _) => Ok(LanguageCodesWithEtag { etag, lang_codes }),
+        (None, Some(cached)) => Ok(cached),
+        // 304 Not Modified although no ETag was sent
+        (None, None) => Err(DpTranError::DeeplApiError(DeeplAPIError::ConnectionError(ConnectionError::Unexpected(304, String::new())))),
+    }
+}
+
+/// Get the target language codes supporting the formality parameter. Using DeepL API.  
+/// api_key: DeepL API key  
+pub fn supported_formality_languages(api_key: &String) -> Result<Vec<LangCode>, DpTranError> {
+    let lang_codes = get_language_codes_with_formality(api_key, LangType::Target)?;
+    Ok(lang_codes.into_iter().filter(|(_, supported)| *supported).map(|(lang, _)| lang.0).collect())
+}
+
+/// Check whether the target language supports the formality parameter. Using DeepL API.  
+/// api_key: DeepL API key  
+/// target_lang: Target language code  
+pub fn formality_supported(api_key: &String, target_lang: &str) -> Result<bool, DpTranError> {
+    let supported_langs = supported_formality_languages(api_key)?;
+    Ok(supported_langs.iter().any(|lang| lang.eq_ignore_ascii_case(target_lang)))
+}
+
 /// Check the validity of language codes. Using DeepL API.  
 /// api_key: DeepL API key  
 /// lang_code: Language code to check  
@@ -77,6 +193,21 @@ pub fn check_language_code(api_key: &String, lang_code: &String, lang_type: Lang
     Ok(false)
 }
 
+/// Check whether the language code is valid as either a source or a target language. Using DeepL API.  
+/// Both lists are fetched once, e.g. EN-GB is valid as it is a target language.  
+/// api_key: DeepL API key  
+/// lang_code: Language code to check  
+pub fn check_language_code_any(api_key: &String, lang_code: &str) -> Result<bool, DpTranError> {
+    let source_lang_codes = get_language_codes(api_key, LangType::Source)?;
+    let target_lang_codes = get_language_codes(api_key, LangType::Target)?;
+    Ok(lang_code_in_any(lang_code, &source_lang_codes, &target_lang_codes))
+}
+
+/// Whether the language code is in the source or target language code list.
+fn lang_code_in_any(lang_code: &str, source_lang_codes: &[LangCodeName], target_lang_codes: &[LangCodeName]) -> bool {
+    source_lang_codes.iter().chain(target_lang_codes).any(|lang| lang.0.eq_ignore_ascii_case(lang_code))
+}
+
 /// Convert to correct language code from input source language code string. Using DeepL API.  
 /// api_key: DeepL API key  
 /// language_code: Language code to convert  
@@ -101,35 +232,435 @@ pub fn correct_target_language_code(api_key: &String, language_code: &str) -> Re
     }
 }
 
+/// Regional variants preferred when a language is specified without region
+/// and only the regional variants are available.
+static PREFERRED_REGIONAL_VARIANTS: [(&str, &str); 3] = [
+    ("EN", "EN-US"),
+    ("PT", "PT-PT"),
+    ("ZH", "ZH-HANS"),
+];
+
+/// Resolve a language code loosely. Using DeepL API.  
+/// In addition to the language codes (case-insensitive), accepts language names (e.g. ``Japanese`` -> ``JA``)  
+/// and languages without region when only the regional variants exist (e.g. ``en`` -> ``EN-US``).  
+/// api_key: DeepL API key  
+/// input: Language code or language name  
+/// lang_type: Target or Source  
+pub fn resolve_language(api_key: &String, input: &str, lang_type: LangType) -> Result<LangCode, DpTranError> {
+    let lang_codes = get_language_codes(api_key, lang_type)?;
+    resolve_language_code(input, &lang_codes).ok_or(DpTranError::InvalidLanguageCode)
+}
+
+/// Resolve a language code loosely from the list.
+fn resolve_language_code(input: &str, lang_codes: &[LangCodeName]) -> Option<LangCode> {
+    let input = input.trim().to_ascii_uppercase();
+
+    // Language code
+    if let Some((code, _)) = lang_codes.iter().find(|(code, _)| code.to_ascii_uppercase() == input) {
+        return Some(code.clone());
+    }
+    // Language name
+    if let Some((code, _)) = lang_codes.iter().find(|(_, name)| name.to_ascii_uppercase() == input) {
+        return Some(code.clone());
+    }
+    // Language without region
+    let variants = lang_codes.iter().filter(|(code, _)| code.to_ascii_uppercase().starts_with(&format!("{}-", input))).collect::<Vec<&LangCodeName>>();
+    if let Some((_, preferred)) = PREFERRED_REGIONAL_VARIANTS.iter().find(|(lang, _)| *lang == input) {
+        if let Some((code, _)) = variants.iter().find(|(code, _)| code.to_ascii_uppercase() == *preferred) {
+            return Some(code.clone());
+        }
+    }
+    variants.first().map(|(code, _)| code.clone())
+}
+
+/// Language codes converted automatically before version 2.1.0.
+static LEGACY_LANG_CODES: [(&str, &str); 2] = [
+    ("EN", "EN-US"),
+    ("PT", "PT-PT"),
+];
+
+/// Expand the language code as before version 2.1.0.  
+/// ``EN`` -> ``EN-US``, ``PT`` -> ``PT-PT``. The other codes are returned in uppercase.  
+/// language_code: Target language code  
+pub fn expand_legacy_language_code(language_code: &str) -> LangCode {
+    let language_code = language_code.to_ascii_uppercase();
+    match LEGACY_LANG_CODES.iter().find(|(legacy, _)| *legacy == language_code) {
+        Some((_, expanded)) => expanded.to_string(),
+        None => language_code,
+    }
+}
+
+/// Suggest the closest valid language code for an invalid input. Using DeepL API.  
+/// Both language codes and language names are compared by edit distance.  
+/// Returns None if there is no close language code.  
+/// api_key: DeepL API key  
+/// input: Invalid language code  
+/// lang_type: Target or Source  
+pub fn suggest_language_code(api_key: &String, input: &str, lang_type: LangType) -> Result<Option<LangCode>, DpTranError> {
+    let lang_codes = get_language_codes(api_key, lang_type)?;
+    Ok(closest_language_code(input, &lang_codes))
+}
+
+/// Find the closest language code to the input from the list.
+fn closest_language_code(input: &str, lang_codes: &[LangCodeName]) -> Option<LangCode> {
+    let input = input.to_ascii_uppercase();
+    // Allow one typo for short codes and two for longer inputs
+    let threshold = if input.len() <= 3 { 1 } else { 2 };
+
+    let mut closest: Option<(usize, &String)> = None;
+    for (code, name) in lang_codes {
+        let distance = levenshtein(&input, &code.to_ascii_uppercase())
+            .min(levenshtein(&input, &name.to_ascii_uppercase()));
+        if distance <= threshold && !matches!(closest, Some((d, _)) if d <= distance) {
+            closest = Some((distance, code));
+        }
+    }
+    closest.map(|(_, code)| code.clone())
+}
+
+/// Levenshtein distance between two strings.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<char>>();
+    let mut prev = (0..=b.len()).collect::<Vec<usize>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// Accept invalid (e.g. self-signed) TLS certificates in the following requests.  
+/// This is intended for environments behind a TLS-inspecting proxy.  
+/// **Unsafe**: the certificate of the server is not verified, so the connection (including the API key) is exposed to man-in-the-middle attacks. Disabled by default.  
+/// accept: true to skip the verification  
+pub fn accept_invalid_certs(accept: bool) {
+    deeplapi::set_accept_invalid_certs(accept);
+}
+
+/// Set the timeout of the connection phase in the following requests.  
+/// A connection that could not be established in time is reported as ``ConnectionError::Offline``.  
+/// timeout: Timeout (None: curl's default of 300 seconds)  
+pub fn set_connect_timeout(timeout: Option<Duration>) {
+    deeplapi::set_connect_timeout(timeout);
+}
+
+/// Set the timeout of the whole request in the following requests.  
+/// A request that timed out after the connection was established is reported as ``ConnectionError::Timeout``.  
+/// timeout: Timeout (None: no timeout)  
+pub fn set_request_timeout(timeout: Option<Duration>) {
+    deeplapi::set_request_timeout(timeout);
+}
+
+/// Set the retry policy of the following requests.  
+/// A request rejected with 429 Too Many Requests or 503 Service Unavailable is sent again, waiting for delay before the first retry and doubling it for each further retry.  
+/// The other errors are never retried, so a translation is not billed twice. No retry by default.  
+/// max_retries: Maximum number of retries (0: no retry)  
+/// delay: Delay before the first retry  
+pub fn set_retry_policy(max_retries: u32, delay: Duration) {
+    deeplapi::set_retry_policy(max_retries, delay);
+}
+
+/// Override the endpoint of the translation in the following requests.  
+/// url: URL of the endpoint (None: ``DEEPL_API_TRANSLATE``)  
+pub fn set_translate_endpoint(url: Option<&str>) {
+    deeplapi::set_translate_endpoint(url);
+}
+
+/// Override the endpoint of the usage in the following requests.  
+/// url: URL of the endpoint (None: ``DEEPL_API_USAGE``)  
+pub fn set_usage_endpoint(url: Option<&str>) {
+    deeplapi::set_usage_endpoint(url);
+}
+
+/// Override the endpoint of the language list in the following requests.  
+/// url: URL of the endpoint (None: ``DEEPL_API_LANGUAGES``)  
+pub fn set_languages_endpoint(url: Option<&str>) {
+    deeplapi::set_languages_endpoint(url);
+}
+
+/// Get the endpoint of the translation used in the following requests.  
+/// Returns the override if set by ``set_translate_endpoint()``, otherwise ``DEEPL_API_TRANSLATE``.  
+pub fn translate_endpoint() -> String {
+    deeplapi::translate_endpoint()
+}
+
+/// Get the endpoint of the usage used in the following requests.  
+/// Returns the override if set by ``set_usage_endpoint()``, otherwise ``DEEPL_API_USAGE``.  
+pub fn usage_endpoint() -> String {
+    deeplapi::usage_endpoint()
+}
+
+/// Get the endpoint of the language list used in the following requests.  
+/// Returns the override if set by ``set_languages_endpoint()``, otherwise ``DEEPL_API_LANGUAGES``.  
+pub fn languages_endpoint() -> String {
+    deeplapi::languages_endpoint()
+}
+
 /// Get the number of characters remaining to be translated. Using DeepL API.  
 /// Retrieved from <https://api-free.deepl.com/v2/usage>.  
 /// Returns an error if acquisition fails.  
 /// api_key: DeepL API key  
 pub fn get_usage(api_key: &String) -> Result<DpTranUsage, DpTranError> {
-    let (count, limit) = deeplapi::get_usage(&api_key).map_err(|e| DpTranError::DeeplApiError(e))?;
+    let (count, limit, document_count, document_limit) = deeplapi::get_usage(&api_key).map_err(|e| DpTranError::DeeplApiError(e))?;
     Ok(DpTranUsage {
         character_count: count,
         character_limit: limit,
         unlimited: limit == 0,
+        document_count,
+        document_limit,
     })
 }
 
+/// Check that the API key and the endpoint are working. Using DeepL API.  
+/// The cheapest authenticated request (usage) is sent, so no characters are consumed.  
+/// The failures are reported as follows:  
+/// ``ApiKeyIsNotSet``: The API key is empty (no request is sent)  
+/// ``ConnectionError::Forbidden``: The API key is invalid or deactivated  
+/// ``ConnectionError::Offline``: The network or the host is unreachable  
+/// ``ConnectionError::NotFound``: The endpoint is wrong  
+/// api_key: DeepL API key  
+pub fn health_check(api_key: &String) -> Result<(), DpTranError> {
+    if api_key.is_empty() {
+        return Err(DpTranError::ApiKeyIsNotSet);
+    }
+    get_usage(api_key).map(|_| ())
+}
+
+/// Check that the endpoint is reachable. Using DeepL API.  
+/// The request is sent without the API key, so no characters are consumed.  
+/// Any HTTP response counts as reachable, even an error status (DeepL API returns 403 without the API key).  
+/// Returns the HTTP status code of the response.  
+/// The failures to get a response (offline, TLS error, timeout, ...) are returned as ``ConnectionError``.  
+/// url: URL of the endpoint (e.g. ``usage_endpoint()``)  
+pub fn check_endpoint(url: &str) -> Result<u32, DpTranError> {
+    deeplapi::check_endpoint(url).map_err(DpTranError::DeeplApiError)
+}
+
 /// Display translation results. Using DeepL API.  
 /// Receive translation results in json format and display translation results.  
 /// Return error if json parsing fails.  
+/// The results are in the same order as ``text``: the n-th result is the translation of the n-th text,
+/// even if the texts are sent in multiple (concurrent) requests.  
 /// api_key: DeepL API key  
 /// text: Text to translate  
 /// target_lang: Target language  
 /// source_lang: Source language (optional)  
+/// If the text is empty or only whitespace, an empty String per text is returned without sending a request.  
 pub fn translate(api_key: &String, text: Vec<String>, target_lang: &String, source_lang: &Option<String>) -> Result<Vec<String>, DpTranError> {
+    if is_blank(&text) {
+        return Ok(vec![String::new(); text.len()]);
+    }
+    check_request_size(api_key, &text, target_lang, source_lang, &TranslateOptions::default())?;
     deeplapi::translate(&api_key, text, target_lang, source_lang).map_err(|e| DpTranError::DeeplApiError(e))
 }
 
+/// Translate a single text. Using DeepL API.  
+/// Same as ``translate()``, but returns the translation as a String.  
+/// api_key: DeepL API key  
+/// text: Text to translate  
+/// target_lang: Target language  
+/// source_lang: Source language (optional)  
+/// If the text is empty or only whitespace, an empty String is returned without sending a request.  
+pub fn translate_text(api_key: &String, text: &str, target_lang: &String, source_lang: &Option<String>) -> Result<String, DpTranError> {
+    let translated = translate(api_key, vec![text.to_string()], target_lang, source_lang)?;
+    Ok(translated.into_iter().next().unwrap_or_default())
+}
+
+/// Translate already-segmented texts. Using DeepL API.  
+/// Each segment is sent as a distinct text as it is (without joining or splitting lines),
+/// and the result has exactly one translation per segment in the same order.  
+/// api_key: DeepL API key  
+/// segments: Segments to translate  
+/// target_lang: Target language  
+/// source_lang: Source language (optional)  
+pub fn translate_segments(api_key: &String, segments: &[String], target_lang: &String, source_lang: &Option<String>) -> Result<Vec<String>, DpTranError> {
+    let translated = translate(api_key, segments.to_vec(), target_lang, source_lang)?;
+    check_segment_count(segments.len(), translated)
+}
+
+/// Check that there is one translation per segment.
+fn check_segment_count(segments: usize, translated: Vec<String>) -> Result<Vec<String>, DpTranError> {
+    if translated.len() != segments {
+        return Err(DpTranError::SegmentCountMismatch(segments, translated.len()));
+    }
+    Ok(translated)
+}
+
+/// Display translation results with a glossary. Using DeepL API.  
+/// api_key: DeepL API key  
+/// text: Text to translate  
+/// target_lang: Target language  
+/// source_lang: Source language (optional)  
+/// glossary_id: Glossary ID. Unknown IDs are reported by DeepL API.  
+/// DeepL API requires the source language with a glossary, so ``GlossaryRequiresSourceLang`` is returned if source_lang is None.  
+/// If the text is empty or only whitespace, an empty String per text is returned without sending a request.  
+pub fn translate_with_glossary(api_key: &String, text: Vec<String>, target_lang: &String, source_lang: &Option<String>, glossary_id: &str) -> Result<Vec<String>, DpTranError> {
+    let options = TranslateOptions::new().with_glossary_id(glossary_id);
+    translate_with_options(api_key, text, target_lang, source_lang, &options)
+}
+
+/// Display translation results with optional parameters. Using DeepL API.  
+/// api_key: DeepL API key  
+/// text: Text to translate  
+/// target_lang: Target language  
+/// source_lang: Source language (optional)  
+/// options: Optional parameters of the translation  
+/// If the text is empty or only whitespace, an empty String per text is returned without sending a request.  
+pub fn translate_with_options(api_key: &String, text: Vec<String>, target_lang: &String, source_lang: &Option<String>, options: &TranslateOptions) -> Result<Vec<String>, DpTranError> {
+    if is_blank(&text) {
+        return Ok(vec![String::new(); text.len()]);
+    }
+    check_translate_options(options, source_lang)?;
+    check_request_size(api_key, &text, target_lang, source_lang, options)?;
+    deeplapi::translate_partial(api_key, text, target_lang, source_lang, options).map_err(|(_, e)| DpTranError::DeeplApiError(e))
+}
+
+/// Check the optional parameters before sending them to DeepL API.
+fn check_translate_options(options: &TranslateOptions, source_lang: &Option<String>) -> Result<(), DpTranError> {
+    if let Some(glossary_id) = &options.glossary_id {
+        // Glossary IDs are UUIDs. Only the characters are checked here.
+        if glossary_id.is_empty() || !glossary_id.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+            return Err(DpTranError::InvalidGlossaryId);
+        }
+        // DeepL API rejects a glossary with the detected source language
+        if source_lang.is_none() {
+            return Err(DpTranError::GlossaryRequiresSourceLang);
+        }
+    }
+    Ok(())
+}
+
+/// Whether the text is empty or only whitespace (nothing to translate).
+fn is_blank(text: &[String]) -> bool {
+    text.iter().all(|line| line.trim().is_empty())
+}
+
+/// Results of the texts with nothing to translate.
+fn blank_translations(len: usize) -> Vec<DpTranTranslation> {
+    vec![DpTranTranslation { text: String::new(), detected_source_language: None, billed_characters: None }; len]
+}
+
+/// Check that each request does not exceed ``DEEPL_API_REQUEST_MAX_BYTES`` before sending.
+/// The size is that of the URL-encoded request body, so multibyte characters count three times their bytes.
+fn check_request_size(api_key: &String, text: &[String], target_lang: &String, source_lang: &Option<String>, options: &TranslateOptions) -> Result<(), DpTranError> {
+    let size = deeplapi::max_request_size(api_key, text, target_lang, source_lang, options);
+    if size > DEEPL_API_REQUEST_MAX_BYTES {
+        return Err(DpTranError::RequestTooLarge(size, DEEPL_API_REQUEST_MAX_BYTES));
+    }
+    Ok(())
+}
+
+/// Translation result with the detected source language.  
+/// ``text``: Translated text  
+/// ``detected_source_language``: Source language detected by DeepL API (None for empty or whitespace-only texts)  
+/// ``billed_characters``: Characters billed for the text (None unless ``TranslateOptions::show_billed_characters`` is set, or for empty or whitespace-only texts)  
+#[derive(Debug, Clone, PartialEq)]
+pub struct DpTranTranslation {
+    pub text: String,
+    pub detected_source_language: Option<String>,
+    pub billed_characters: Option<u64>,
+}
+
+/// Convert the results of DeepL API to ``DpTranTranslation``.
+fn to_translations(translated: Vec<deeplapi::TranslationDetail>) -> Vec<DpTranTranslation> {
+    translated.into_iter().map(|(text, detected_source_language, billed_characters)| DpTranTranslation { text, detected_source_language, billed_characters }).collect()
+}
+
+/// Display translation results with the detected source languages. Using DeepL API.  
+/// api_key: DeepL API key  
+/// text: Text to translate  
+/// target_lang: Target language  
+/// source_lang: Source language (optional)  
+/// options: Optional parameters of the translation  
+/// If the text is empty or only whitespace, an empty translation per text is returned without sending a request.  
+pub fn translate_detailed(api_key: &String, text: Vec<String>, target_lang: &String, source_lang: &Option<String>, options: &TranslateOptions) -> Result<Vec<DpTranTranslation>, DpTranError> {
+    if is_blank(&text) {
+        return Ok(blank_translations(text.len()));
+    }
+    check_translate_options(options, source_lang)?;
+    check_request_size(api_key, &text, target_lang, source_lang, options)?;
+    let translated = deeplapi::translate_detailed(api_key, text, target_lang, source_lang, options).map_err(DpTranError::DeeplApiError)?;
+    Ok(to_translations(translated))
+}
+
+/// Display translation results. Using DeepL API.  
+/// Same as ``translate()``, but if the translation fails partway through,
+/// the results translated so far are returned in ``DpTranPartialError``.  
+/// api_key: DeepL API key  
+/// text: Text to translate  
+/// target_lang: Target language  
+/// source_lang: Source language (optional)  
+/// options: Optional parameters of the translation  
+/// If the text is empty or only whitespace, an empty String per text is returned without sending a request.  
+pub fn translate_partial(api_key: &String, text: Vec<String>, target_lang: &String, source_lang: &Option<String>, options: &TranslateOptions) -> Result<Vec<String>, DpTranPartialError> {
+    if is_blank(&text) {
+        return Ok(vec![String::new(); text.len()]);
+    }
+    let len = text.len();
+    check_translate_options(options, source_lang).and_then(|_| check_request_size(api_key, &text, target_lang, source_lang, options)).map_err(|e| DpTranPartialError {
+        translated: Vec::new(),
+        failed_range: 0..len,
+        error: e,
+    })?;
+    deeplapi::translate_partial(api_key, text, target_lang, source_lang, options).map_err(|(translated, e)| DpTranPartialError {
+        failed_range: translated.len()..len,
+        translated,
+        error: DpTranError::DeeplApiError(e),
+    })
+}
+
+/// Display translation results with the detected source languages. Using DeepL API.  
+/// Same as ``translate_partial()``, but the results are returned as ``DpTranTranslation``
+/// (e.g. with the billed characters if ``show_billed_characters`` is set).  
+/// api_key: DeepL API key  
+/// text: Text to translate  
+/// target_lang: Target language  
+/// source_lang: Source language (optional)  
+/// options: Optional parameters of the translation  
+/// If the text is empty or only whitespace, an empty translation per text is returned without sending a request.  
+pub fn translate_partial_detailed(api_key: &String, text: Vec<String>, target_lang: &String, source_lang: &Option<String>, options: &TranslateOptions) -> Result<Vec<DpTranTranslation>, DpTranPartialError> {
+    if is_blank(&text) {
+        return Ok(blank_translations(text.len()));
+    }
+    let len = text.len();
+    check_translate_options(options, source_lang).and_then(|_| check_request_size(api_key, &text, target_lang, source_lang, options)).map_err(|e| DpTranPartialError {
+        translated: Vec::new(),
+        failed_range: 0..len,
+        error: e,
+    })?;
+    deeplapi::translate_partial_detailed(api_key, text, target_lang, source_lang, options).map(to_translations).map_err(|(translated, e)| DpTranPartialError {
+        failed_range: translated.len()..len,
+        translated: translated.into_iter().map(|(text, _, _)| text).collect(),
+        error: DpTranError::DeeplApiError(e),
+    })
+}
+
+/// Display translation results with placeholders protected. Using DeepL API.  
+/// The placeholders matching the patterns (e.g. ``{name}``, ``%s``, ``{{count}}``) are replaced with neutral tokens before sending,
+/// and restored in the translation results, so that they are not translated.  
+/// api_key: DeepL API key  
+/// text: Text to translate  
+/// target_lang: Target language  
+/// source_lang: Source language (optional)  
+/// patterns: Regular expressions of the placeholders. See ``DEFAULT_PLACEHOLDER_PATTERNS``.  
+/// If the text is empty or only whitespace, an empty String per text is returned without sending a request.  
+pub fn translate_protected(api_key: &String, text: Vec<String>, target_lang: &String, source_lang: &Option<String>, patterns: &[String]) -> Result<Vec<String>, DpTranError> {
+    let re = placeholder::build_regex(patterns).map_err(|e| DpTranError::InvalidPlaceholderPattern(e.to_string()))?;
+    let (masked, placeholders): (Vec<String>, Vec<Vec<String>>) = text.iter().map(|t| placeholder::mask(t, &re)).unzip();
+    let translated = translate(api_key, masked, target_lang, source_lang)?;
+    Ok(translated.iter().zip(placeholders.iter()).map(|(t, p)| placeholder::unmask(t, p)).collect())
+}
+
 #[test]
 /// run with `cargo test api_tests -- <api_key> <DeepL API free = 0, DeepL API pro = 1>`
 /// arg[2] : api_key
 /// arg[3] : DeepL API free = 0, DeepL API pro = 1
 fn lib_tests() {
+    let _lock = deeplapi::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
     if std::env::args().len() < 3 {
         panic!("Usage: cargo test lib_tests -- <api_key> <DeepL API free = 0, DeepL API pro = 1>");
     }
@@ -156,6 +687,18 @@ fn lib_tests() {
         }
     }
 
+    // translate_text test
+    let res = translate_text(api_key, "Hello, World!", &target_lang, &source_lang);
+    match res {
+        Ok(res) => {
+            assert!(!res.is_empty());
+            println!("res: {}", res);
+        },
+        Err(e) => {
+            panic!("Error: {}", e.to_string());
+        }
+    }
+
     // usage test
     let res = get_usage(api_key);
     match res {
@@ -206,3 +749,216 @@ fn lib_tests() {
         }
     }
 }
+
+#[test]
+fn translate_with_glossary_test() {
+    // Invalid glossary IDs are rejected before sending the request.
+    let text = vec!["Hello, World!".to_string()];
+    let res = translate_with_glossary(&"".to_string(), text, &"JA".to_string(), &Some("EN".to_string()), "not a glossary");
+    assert_eq!(res, Err(DpTranError::InvalidGlossaryId));
+    let res = check_translate_options(&TranslateOptions { glossary_id: Some("def3a26b-3e84-45b3-84ae-0c0aaf3525f7".to_string()), ..Default::default() }, &Some("EN".to_string()));
+    assert_eq!(res, Ok(()));
+    // The source language is required with a glossary
+    let text = vec!["Hello, World!".to_string()];
+    let res = translate_with_glossary(&"".to_string(), text, &"JA".to_string(), &None, "def3a26b-3e84-45b3-84ae-0c0aaf3525f7");
+    assert_eq!(res, Err(DpTranError::GlossaryRequiresSourceLang));
+}
+
+#[test]
+fn closest_language_code_test() {
+    let lang_codes = vec![
+        ("DE".to_string(), "German".to_string()),
+        ("EN".to_string(), "English".to_string()),
+        ("EN-GB".to_string(), "English (British)".to_string()),
+        ("EN-US".to_string(), "English (American)".to_string()),
+        ("JA".to_string(), "Japanese".to_string()),
+    ];
+    assert_eq!(closest_language_code("JP", &lang_codes), Some("JA".to_string()));
+    assert_eq!(closest_language_code("ENGLISH", &lang_codes), Some("EN".to_string()));
+    assert_eq!(closest_language_code("japanes", &lang_codes), Some("JA".to_string()));
+    assert_eq!(closest_language_code("QWERTYUIOP", &lang_codes), None);
+}
+
+#[test]
+fn resolve_language_code_test() {
+    let lang_codes = vec![
+        ("DE".to_string(), "German".to_string()),
+        ("EN-GB".to_string(), "English (British)".to_string()),
+        ("EN-US".to_string(), "English (American)".to_string()),
+        ("JA".to_string(), "Japanese".to_string()),
+        ("PT-BR".to_string(), "Portuguese (Brazilian)".to_string()),
+        ("PT-PT".to_string(), "Portuguese (European)".to_string()),
+    ];
+    // name
+    assert_eq!(resolve_language_code("Japanese", &lang_codes), Some("JA".to_string()));
+    assert_eq!(resolve_language_code("english (british)", &lang_codes), Some("EN-GB".to_string()));
+    // bare
+    assert_eq!(resolve_language_code("en", &lang_codes), Some("EN-US".to_string()));
+    assert_eq!(resolve_language_code("pt", &lang_codes), Some("PT-PT".to_string()));
+    // regional
+    assert_eq!(resolve_language_code("en-gb", &lang_codes), Some("EN-GB".to_string()));
+    assert_eq!(resolve_language_code("de", &lang_codes), Some("DE".to_string()));
+    // invalid
+    assert_eq!(resolve_language_code("xx", &lang_codes), None);
+}
+
+#[test]
+fn request_too_large_test() {
+    // Rejected before sending, so the API key is not used
+    let api_key = "dummy".to_string();
+    let target_lang = "JA".to_string();
+    let options = TranslateOptions::default();
+    let text = vec!["a".repeat(DEEPL_API_REQUEST_MAX_BYTES + 1)];
+    let size = deeplapi::max_request_size(&api_key, &text, &target_lang, &None, &options);
+    assert!(size > DEEPL_API_REQUEST_MAX_BYTES + 1);
+    let res = translate(&api_key, text.clone(), &target_lang, &None);
+    assert_eq!(res, Err(DpTranError::RequestTooLarge(size, DEEPL_API_REQUEST_MAX_BYTES)));
+    let res = translate_partial(&api_key, text, &target_lang, &None, &options);
+    assert_eq!(res.unwrap_err().failed_range, 0..1);
+
+    // Multibyte characters are URL-encoded (3 bytes -> 9 bytes), so the encoded body is measured
+    let overhead = deeplapi::max_request_size(&api_key, &["a".to_string()], &target_lang, &None, &options) - 1;
+    let fit = (DEEPL_API_REQUEST_MAX_BYTES - overhead) / 9;
+    let text = vec!["あ".repeat(fit)];
+    assert_eq!(check_request_size(&api_key, &text, &target_lang, &None, &options), Ok(()));
+    let text = vec!["あ".repeat(fit + 1)];
+    assert_eq!(check_request_size(&api_key, &text, &target_lang, &None, &options), Err(DpTranError::RequestTooLarge(overhead + (fit + 1) * 9, DEEPL_API_REQUEST_MAX_BYTES)));
+    // Raw bytes under the limit can still exceed it once encoded
+    let text = vec!["あ".repeat(DEEPL_API_REQUEST_MAX_BYTES / 3 - 1)];
+    assert!(check_request_size(&api_key, &text, &target_lang, &None, &options).is_err());
+
+    // Texts are sent in batches (up to 50 texts), so the size of each batch is checked
+    let text = vec!["a".repeat(DEEPL_API_REQUEST_MAX_BYTES / 100); 150];
+    assert_eq!(check_request_size(&api_key, &text, &target_lang, &None, &options), Ok(()));
+    // Blank texts are not sent
+    let text = vec!["あ".repeat(fit), " ".repeat(DEEPL_API_REQUEST_MAX_BYTES)];
+    assert_eq!(check_request_size(&api_key, &text, &target_lang, &None, &options), Ok(()));
+}
+
+#[test]
+fn check_segment_count_test() {
+    let translated = vec!["一。".to_string(), "二。三。".to_string(), "四。".to_string()];
+    assert_eq!(check_segment_count(3, translated.clone()), Ok(translated.clone()));
+    assert_eq!(check_segment_count(4, translated), Err(DpTranError::SegmentCountMismatch(4, 3)));
+}
+
+#[test]
+fn empty_input_test() {
+    let _lock = deeplapi::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    // No request is made: the server does not receive any connection
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    set_translate_endpoint(Some(&format!("http://{}/v2/translate", listener.local_addr().unwrap())));
+
+    let api_key = "dummy".to_string();
+    let target_lang = "JA".to_string();
+    let source_lang = Some("EN".to_string());
+    let options = TranslateOptions::new().with_formality(Formality::More);
+    let blank = vec![" \t".to_string(), "".to_string()];
+    let results = (
+        translate(&api_key, vec![], &target_lang, &None),
+        translate(&api_key, vec!["".to_string()], &target_lang, &None),
+        // One result per text, as for the texts with something to translate
+        translate(&api_key, blank.clone(), &target_lang, &None),
+        translate_segments(&api_key, &["  ".to_string()], &target_lang, &None),
+        translate_text(&api_key, " \n", &target_lang, &None),
+        translate_with_glossary(&api_key, blank.clone(), &target_lang, &source_lang, "def3a26b-3e84-45b3-84ae-0c0aaf3525f7"),
+        translate_with_options(&api_key, blank.clone(), &target_lang, &None, &options),
+        translate_detailed(&api_key, blank.clone(), &target_lang, &None, &options),
+        translate_partial(&api_key, blank.clone(), &target_lang, &None, &options),
+        translate_partial_detailed(&api_key, blank.clone(), &target_lang, &None, &options),
+        translate_protected(&api_key, blank.clone(), &target_lang, &None, &["%s".to_string()]),
+    );
+    let accepted = listener.accept();
+    set_translate_endpoint(None);
+    assert!(matches!(accepted, Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock), "a request was sent: {:?}", accepted);
+
+    let empty = vec![String::new(), String::new()];
+    assert_eq!(results.0, Ok(Vec::new()));
+    assert_eq!(results.1, Ok(vec![String::new()]));
+    assert_eq!(results.2, Ok(empty.clone()));
+    assert_eq!(results.3, Ok(vec![String::new()]));
+    assert_eq!(results.4, Ok(String::new()));
+    assert_eq!(results.5, Ok(empty.clone()));
+    assert_eq!(results.6, Ok(empty.clone()));
+    assert_eq!(results.7, Ok(blank_translations(2)));
+    assert_eq!(results.8.unwrap(), empty.clone());
+    assert_eq!(results.9.unwrap(), blank_translations(2));
+    assert_eq!(results.10, Ok(empty));
+}
+
+#[test]
+fn health_check_test() {
+    // No request is sent without the API key
+    assert_eq!(health_check(&"".to_string()), Err(DpTranError::ApiKeyIsNotSet));
+}
+
+#[test]
+fn language_codes_etag_test() {
+    let _lock = deeplapi::TEST_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    // Server returning the list with its ETag, then 304 Not Modified
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    set_languages_endpoint(Some(&format!("http://{}/v2/languages", listener.local_addr().unwrap())));
+    let server = std::thread::spawn(move || {
+        for i in 0..3 {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0; 4096];
+            let _ = stream.read(&mut buf).unwrap();
+            let response = if i == 0 {
+                let body = r#"[{"language":"JA","name":"Japanese","supports_formality":true}]"#;
+                format!("HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+            } else {
+                "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n".to_string()
+            };
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+
+    let api_key = "dummy".to_string();
+    let first = get_language_codes_with_etag(&api_key, LangType::Target, None);
+    // The cached list is used on 304
+    let second = get_language_codes_with_etag(&api_key, LangType::Target, first.as_ref().ok().cloned());
+    // 304 without the cached list
+    let third = get_language_codes_with_etag(&api_key, LangType::Target, None);
+    set_languages_endpoint(None);
+    server.join().unwrap();
+
+    let first = first.unwrap();
+    assert_eq!(first.etag, Some("\"v1\"".to_string()));
+    assert!(first.lang_codes.contains(&(("JA".to_string(), "Japanese".to_string()), true)));
+    assert_eq!(second, Ok(first));
+    assert_eq!(third, Err(DpTranError::DeeplApiError(DeeplAPIError::ConnectionError(ConnectionError::Unexpected(304, String::new())))));
+}
+
+#[test]
+fn lang_code_in_any_test() {
+    let source_lang_codes = vec![("EN".to_string(), "English".to_string()), ("JA".to_string(), "Japanese".to_string())];
+    let target_lang_codes = vec![("EN-GB".to_string(), "English (British)".to_string()), ("JA".to_string(), "Japanese".to_string())];
+    // Only a target language
+    assert!(lang_code_in_any("EN-GB", &source_lang_codes, &target_lang_codes));
+    // Only a source language
+    assert!(lang_code_in_any("en", &source_lang_codes, &target_lang_codes));
+    assert!(lang_code_in_any("JA", &source_lang_codes, &target_lang_codes));
+    assert!(!lang_code_in_any("XX", &source_lang_codes, &target_lang_codes));
+}
+
+#[test]
+fn usage_display_test() {
+    let mut usage = DpTranUsage {
+        character_count: 1234,
+        character_limit: 500000,
+        unlimited: false,
+        document_count: None,
+        document_limit: None,
+    };
+    assert_eq!(usage.to_string(), "1234 / 500000 (0%)");
+    usage.character_count = 250000;
+    assert_eq!(usage.to_string(), "250000 / 500000 (50%)");
+
+    usage.character_limit = 0;
+    usage.unlimited = true;
+    assert_eq!(usage.to_string(), "250000 / unlimited");
+}
@@ -0,0 +1,83 @@
+//! Protection of placeholders (interpolation tokens) from translation
+
+use regex::Regex;
+
+/// Default placeholder patterns.
+/// ``{{count}}``, ``{name}``, ``%s``, ``%d``, ``%1$s`` etc.
+pub static DEFAULT_PLACEHOLDER_PATTERNS: [&str; 3] = [
+    r"\{\{[^{}]*\}\}",
+    r"\{[^{}]*\}",
+    r"%(\d+\$)?[sdif]",
+];
+
+/// Pattern of the neutral tokens. See ``token()``.
+const TOKEN_PATTERN: &str = r"__PH(\d+)__";
+
+/// Build a single regex matching any of the patterns.
+/// Earlier patterns take precedence over later ones.
+/// The text looking like a neutral token is also matched, so that it is masked and restored as it is.
+pub fn build_regex(patterns: &[String]) -> Result<Regex, regex::Error> {
+    let joined = std::iter::once(TOKEN_PATTERN).chain(patterns.iter().map(|p| p.as_str()))
+        .map(|p| format!("(?:{})", p)).collect::<Vec<String>>().join("|");
+    Regex::new(&joined)
+}
+
+/// Neutral token that replaces the n-th placeholder in a line.
+fn token(n: usize) -> String {
+    format!("__PH{}__", n)
+}
+
+/// Replace the placeholders in the text with neutral tokens.
+/// Returns the masked text and the original placeholders in order of appearance.
+pub fn mask(text: &str, re: &Regex) -> (String, Vec<String>) {
+    let mut placeholders = Vec::new();
+    let masked = re.replace_all(text, |caps: &regex::Captures| {
+        placeholders.push(caps[0].to_string());
+        token(placeholders.len() - 1)
+    });
+    (masked.to_string(), placeholders)
+}
+
+/// Restore the placeholders replaced by ``mask()``.
+/// The tokens are replaced in a single pass, so the restored placeholders are never replaced again.
+/// The tokens without a placeholder are left as they are.
+pub fn unmask(text: &str, placeholders: &[String]) -> String {
+    let re = Regex::new(TOKEN_PATTERN).expect("invalid token pattern");
+    re.replace_all(text, |caps: &regex::Captures| {
+        caps[1].parse::<usize>().ok()
+            .and_then(|n| placeholders.get(n))
+            .cloned()
+            .unwrap_or_else(|| caps[0].to_string())
+    }).to_string()
+}
+
+#[test]
+fn mask_unmask_test() {
+    let patterns = DEFAULT_PLACEHOLDER_PATTERNS.iter().map(|p| p.to_string()).collect::<Vec<String>>();
+    let re = build_regex(&patterns).unwrap();
+
+    let (masked, placeholders) = mask("Hello {user}, you have %d new messages.", &re);
+    assert_eq!(masked, "Hello __PH0__, you have __PH1__ new messages.");
+    assert_eq!(placeholders, vec!["{user}".to_string(), "%d".to_string()]);
+
+    // Pretend translation which keeps the tokens in place
+    let translated = masked.replace("Hello", "こんにちは").replace("you have", "").replace("new messages.", "件の新着メッセージがあります。");
+    let restored = unmask(&translated, &placeholders);
+    assert_eq!(restored, "こんにちは {user},  %d 件の新着メッセージがあります。");
+    assert!(restored.find("{user}").unwrap() < restored.find("%d").unwrap());
+
+    // {{count}} is protected as a whole
+    let (masked, placeholders) = mask("{{count}} items", &re);
+    assert_eq!(masked, "__PH0__ items");
+    assert_eq!(placeholders, vec!["{{count}}".to_string()]);
+
+    // The text already containing the token pattern round-trips
+    for text in ["__PH1__ {user} __PH0__", "{__PH0__} %s", "__PH12__%d"] {
+        let (masked, placeholders) = mask(text, &re);
+        assert_eq!(unmask(&masked, &placeholders), text);
+    }
+    // A restored placeholder is not replaced again
+    assert_eq!(unmask("__PH0__ __PH1__", &["__PH1__".to_string(), "{user}".to_string()]), "__PH1__ {user}");
+    // Unknown tokens are left as they are
+    assert_eq!(unmask("__PH5__", &[]), "__PH5__");
+}